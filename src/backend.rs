@@ -0,0 +1,434 @@
+use crate::error::{Result, SwayspaceError};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use swayipc::reply::{Event, Node, NodeType, WindowChange, WorkspaceChange};
+use swayipc::{Connection, EventType};
+
+#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Serialize)]
+pub struct Output {
+    pub x_pos: i64,
+    pub y_pos: i64,
+    pub name: String,
+    pub width: i64,
+    pub height: i64,
+    pub make: String,
+    pub model: String,
+    pub serial: String,
+}
+
+impl Output {
+    // Like sway's own output identifiers, the same whatever it is plugged into
+    pub fn identifier(&self) -> String {
+        format!("{} {} {}", self.make, self.model, self.serial)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    // -1 for workspaces whose name does not start with a number
+    pub num: i32,
+    pub name: String,
+    pub output: String,
+    pub visible: bool,
+    pub focused: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Window {
+    // Sway's con_id
+    pub id: i64,
+    pub app_id: Option<String>,
+    pub workspace: String,
+    pub focused: bool,
+    // Marked `SHOWN`
+    pub shown: bool,
+}
+
+// The hidden workspace that sway keeps scratchpad windows on while they are
+// not shown
+pub const SCRATCHPAD: &str = "__i3_scratch";
+
+// Marks the window swayspace last showed from the scratchpad. Sway keeps each
+// mark on a single window, and does not draw those starting with `_`.
+pub const SHOWN: &str = "_swayspace_shown";
+
+// Steps sent to sway as a single message, separated by `;`, so that they are
+// applied together: no intermediate frame is drawn and no other client gets
+// to run commands in between.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CommandBatch(Vec<String>);
+
+impl CommandBatch {
+    pub fn then(mut self, step: impl Into<String>) -> Self {
+        self.push(step);
+        self
+    }
+    pub fn push(&mut self, step: impl Into<String>) {
+        self.0.push(step.into());
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn steps(&self) -> &[String] {
+        &self.0
+    }
+}
+
+impl FromIterator<String> for CommandBatch {
+    fn from_iter<I: IntoIterator<Item = String>>(steps: I) -> Self {
+        Self(steps.into_iter().collect())
+    }
+}
+
+impl fmt::Display for CommandBatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.join("; "))
+    }
+}
+
+// Everything swayspace needs from the window manager. Abstracted so that the
+// navigation logic can be exercised without a running sway session.
+pub trait WindowManager {
+    // The focused output's name, along with what `get_windows` gives. Both
+    // come from sway's tree, which is worth asking for only once.
+    fn get_focused_output_and_windows(&mut self) -> Result<(String, Vec<Window>)>;
+    fn get_outputs(&mut self) -> Result<Vec<Output>>;
+    fn get_workspaces(&mut self) -> Result<Vec<Workspace>>;
+    // Workspace by workspace, tiled windows before floating ones, each in
+    // the order of the tree
+    fn get_windows(&mut self) -> Result<Vec<Window>>;
+    // Sway's con_id of each workspace, by name. Unlike names, they survive
+    // renames.
+    fn get_workspace_ids(&mut self) -> Result<HashMap<String, i64>>;
+    // One outcome per `;`-separated step that was attempted, with the reason
+    // for the failure of those that sway refused
+    fn run_command(&mut self, command: &str) -> Result<Vec<std::result::Result<(), String>>>;
+    // Sends the whole batch as one message. Fails with `CommandRejected`
+    // naming the first step that sway refused.
+    fn run(&mut self, batch: &CommandBatch) -> Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let outcomes = self.run_command(&batch.to_string())?;
+        match batch
+            .steps()
+            .iter()
+            .zip(outcomes)
+            .find_map(|(step, outcome)| outcome.err().map(|reason| (step, reason)))
+        {
+            Some((step, reason)) => Err(SwayspaceError::CommandRejected {
+                command: step.clone(),
+                reason,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+// What a sway event changed, as far as swayspace is concerned. Outputs only
+// show through their workspaces: sway creates or moves one when they come
+// and go, and swayipc 2.7 has no output events of its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    // Workspaces were created, destroyed, renamed or moved
+    Workspace,
+    // Only which workspace has focus
+    WorkspaceFocus(String),
+    // Windows were opened, closed or moved around
+    Window,
+    // Only which window has focus
+    WindowFocus(i64),
+}
+
+pub struct Sway(Connection);
+
+impl Sway {
+    pub fn connect() -> Result<Self> {
+        Connection::new().map(Self).map_err(unavailable)
+    }
+    // Blocks between events, on a connection of its own. Titles, marks and
+    // the like are left out: nothing here depends on them.
+    pub fn subscribe() -> Result<impl Iterator<Item = Result<Change>>> {
+        let events = Connection::new()
+            .map_err(unavailable)?
+            .subscribe(&[EventType::Workspace, EventType::Window])
+            .map_err(ipc_error)?;
+        Ok(events.filter_map(|event| match event {
+            Ok(Event::Workspace(e)) => match (&e.change, e.current) {
+                (
+                    WorkspaceChange::Focus,
+                    Some(Node {
+                        name: Some(name), ..
+                    }),
+                ) => Some(Ok(Change::WorkspaceFocus(name))),
+                (WorkspaceChange::Urgent, _) => None,
+                _ => Some(Ok(Change::Workspace)),
+            },
+            Ok(Event::Window(e)) => match &e.change {
+                WindowChange::Focus => Some(Ok(Change::WindowFocus(e.container.id))),
+                WindowChange::New
+                | WindowChange::Close
+                | WindowChange::Move
+                | WindowChange::Floating => Some(Ok(Change::Window)),
+                _ => None,
+            },
+            Ok(_) => None,
+            Err(e) => Some(Err(ipc_error(e))),
+        }))
+    }
+}
+
+// Windows are the leaves of the tree. Only workspaces have floating nodes,
+// so going through the tiled ones first keeps the tiling order.
+fn collect_windows(node: &Node, workspace: Option<&str>, windows: &mut Vec<Window>) {
+    let workspace = match (&node.node_type, &node.name) {
+        (NodeType::Workspace, Some(name)) => Some(name.as_str()),
+        _ => workspace,
+    };
+    let leaf = node.nodes.is_empty() && node.floating_nodes.is_empty();
+    match (leaf, &node.node_type, workspace) {
+        (true, NodeType::Con | NodeType::FloatingCon, Some(workspace)) => windows.push(Window {
+            id: node.id,
+            app_id: node.app_id.clone(),
+            workspace: workspace.to_string(),
+            focused: node.focused,
+            shown: node.marks.iter().any(|mark| mark == SHOWN),
+        }),
+        _ => node
+            .nodes
+            .iter()
+            .chain(&node.floating_nodes)
+            .for_each(|child| collect_windows(child, workspace, windows)),
+    }
+}
+
+// Workspaces never nest, so there is no need to look inside them
+fn collect_workspace_ids(node: &Node, ids: &mut HashMap<String, i64>) {
+    match (&node.node_type, &node.name) {
+        (NodeType::Workspace, Some(name)) => {
+            ids.insert(name.clone(), node.id);
+        }
+        _ => node
+            .nodes
+            .iter()
+            .for_each(|child| collect_workspace_ids(child, ids)),
+    }
+}
+
+fn unavailable(e: impl Display) -> SwayspaceError {
+    SwayspaceError::IpcUnavailable(e.to_string())
+}
+
+fn ipc_error(e: impl Display) -> SwayspaceError {
+    SwayspaceError::Ipc(e.to_string())
+}
+
+impl WindowManager for Sway {
+    fn get_focused_output_and_windows(&mut self) -> Result<(String, Vec<Window>)> {
+        let tree = self.0.get_tree().map_err(ipc_error)?;
+        let mut windows = Vec::new();
+        collect_windows(&tree, None, &mut windows);
+        let focused_output = tree
+            .find_focused(|node| matches!(node.node_type, NodeType::Output))
+            .and_then(|output| output.name)
+            .ok_or(SwayspaceError::NoFocusedOutput)?;
+        Ok((focused_output, windows))
+    }
+    fn get_outputs(&mut self) -> Result<Vec<Output>> {
+        Ok(self
+            .0
+            .get_outputs()
+            .map_err(ipc_error)?
+            .iter()
+            .map(|o| Output {
+                x_pos: o.rect.x,
+                y_pos: o.rect.y,
+                name: o.name.clone(),
+                width: o.rect.width,
+                height: o.rect.height,
+                make: o.make.clone(),
+                model: o.model.clone(),
+                serial: o.serial.clone(),
+            })
+            .collect())
+    }
+    fn get_workspaces(&mut self) -> Result<Vec<Workspace>> {
+        Ok(self
+            .0
+            .get_workspaces()
+            .map_err(ipc_error)?
+            .into_iter()
+            .map(|w| Workspace {
+                num: w.num,
+                name: w.name,
+                output: w.output,
+                visible: w.visible,
+                focused: w.focused,
+            })
+            .collect())
+    }
+    fn get_windows(&mut self) -> Result<Vec<Window>> {
+        let mut windows = Vec::new();
+        collect_windows(&self.0.get_tree().map_err(ipc_error)?, None, &mut windows);
+        Ok(windows)
+    }
+    fn get_workspace_ids(&mut self) -> Result<HashMap<String, i64>> {
+        let mut ids = HashMap::new();
+        collect_workspace_ids(&self.0.get_tree().map_err(ipc_error)?, &mut ids);
+        Ok(ids)
+    }
+    fn run_command(&mut self, command: &str) -> Result<Vec<std::result::Result<(), String>>> {
+        Ok(self
+            .0
+            .run_command(command)
+            .map_err(ipc_error)?
+            .into_iter()
+            .map(|outcome| match outcome {
+                outcome if outcome.success => Ok(()),
+                outcome => Err(outcome.error.unwrap_or_else(|| "unknown error".to_string())),
+            })
+            .collect())
+    }
+}
+
+// In-memory window manager: holds a fixed layout and records the messages it
+// is asked to run instead of executing them.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct FakeWindowManager {
+    pub outputs: Vec<Output>,
+    pub workspaces: Vec<Workspace>,
+    pub windows: Vec<Window>,
+    pub commands: Vec<String>,
+    // Steps starting with this prefix are refused, and like sway, the ones
+    // after them are not attempted
+    pub rejecting: Option<String>,
+}
+
+#[cfg(test)]
+impl FakeWindowManager {
+    // All outputs are 1920x1080, and their model is their name
+    pub fn with_output(mut self, name: &str, x_pos: i64, y_pos: i64) -> Self {
+        self.outputs.push(Output {
+            x_pos,
+            y_pos,
+            name: name.to_string(),
+            width: 1920,
+            height: 1080,
+            make: "Fake".to_string(),
+            model: name.to_string(),
+            serial: "0".to_string(),
+        });
+        self
+    }
+    pub fn with_workspace(mut self, name: &str, output: &str) -> Self {
+        // Same rule as sway: the number is whatever the name starts with
+        let digits = name
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect::<String>();
+        self.workspaces.push(Workspace {
+            num: digits.parse().unwrap_or(-1),
+            name: name.to_string(),
+            output: output.to_string(),
+            visible: false,
+            focused: false,
+        });
+        self
+    }
+    // Windows get their ids in the order they are added, from 1
+    pub fn with_window(mut self, workspace: &str, app_id: &str) -> Self {
+        self.windows.push(Window {
+            id: self.windows.len() as i64 + 1,
+            app_id: Some(app_id.to_string()),
+            workspace: workspace.to_string(),
+            focused: false,
+            shown: false,
+        });
+        self
+    }
+    // As if swayspace had shown it from the scratchpad
+    pub fn shown(mut self, id: i64) -> Self {
+        self.windows[id as usize - 1].shown = true;
+        self
+    }
+    pub fn with_windows(self, workspace: &str, count: usize) -> Self {
+        (0..count).fold(self, |wm, _| wm.with_window(workspace, "app"))
+    }
+    // Focuses the workspace of the window too
+    pub fn focusing_window(mut self, id: i64) -> Self {
+        let workspace = self.windows[id as usize - 1].workspace.clone();
+        self = self.focusing(&workspace);
+        for w in self.windows.iter_mut() {
+            w.focused = w.id == id;
+        }
+        self
+    }
+    // Makes `name` the visible workspace of its output
+    pub fn showing(mut self, name: &str) -> Self {
+        let output = self.workspace(name).output.clone();
+        for w in self.workspaces.iter_mut().filter(|w| w.output == output) {
+            w.visible = w.name == name;
+        }
+        self
+    }
+    // Makes `name` visible and focused, which also focuses its output
+    pub fn focusing(mut self, name: &str) -> Self {
+        self = self.showing(name);
+        for w in self.workspaces.iter_mut() {
+            w.focused = w.name == name;
+        }
+        self
+    }
+    fn workspace(&self, name: &str) -> &Workspace {
+        self.workspaces.iter().find(|w| w.name == name).unwrap()
+    }
+}
+
+#[cfg(test)]
+impl WindowManager for FakeWindowManager {
+    fn get_focused_output_and_windows(&mut self) -> Result<(String, Vec<Window>)> {
+        let focused_output = self
+            .workspaces
+            .iter()
+            .find(|w| w.focused)
+            .map(|w| w.output.clone())
+            .ok_or(SwayspaceError::NoFocusedOutput)?;
+        Ok((focused_output, self.windows.clone()))
+    }
+    fn get_outputs(&mut self) -> Result<Vec<Output>> {
+        Ok(self.outputs.clone())
+    }
+    fn get_workspaces(&mut self) -> Result<Vec<Workspace>> {
+        Ok(self.workspaces.clone())
+    }
+    fn get_windows(&mut self) -> Result<Vec<Window>> {
+        Ok(self.windows.clone())
+    }
+    // Workspaces keep the id of their position, from 1, through renames
+    fn get_workspace_ids(&mut self) -> Result<HashMap<String, i64>> {
+        Ok(self
+            .workspaces
+            .iter()
+            .enumerate()
+            .map(|(i, w)| (w.name.clone(), i as i64 + 1))
+            .collect())
+    }
+    fn run_command(&mut self, command: &str) -> Result<Vec<std::result::Result<(), String>>> {
+        self.commands.push(command.to_string());
+        let rejected = |step: &str| match &self.rejecting {
+            Some(prefix) => step.starts_with(prefix.as_str()),
+            None => false,
+        };
+        let mut outcomes = Vec::new();
+        for step in command.split("; ") {
+            if rejected(step) {
+                outcomes.push(Err("rejected by fake".to_string()));
+                break;
+            }
+            outcomes.push(Ok(()));
+        }
+        Ok(outcomes)
+    }
+}
@@ -0,0 +1,118 @@
+use crate::backend::{Change, Sway};
+use crate::config::{Config, Settings};
+use crate::error::Result;
+use crate::state::WindowManagerState;
+use crate::{AtEnd, Direction, Opt};
+use serde_json::json;
+use std::io::{self, Write};
+
+// Prints a line for waybar's custom modules (`"return-type": "json"`) every
+// time the workspaces change, until sway goes away
+pub fn run(opt: &Opt, config: &Config) -> Result<()> {
+    let mut wm = Sway::connect()?;
+    let mut last = String::new();
+    // Windows coming and going only matter when empty workspaces are skipped
+    let mut skip_empty = false;
+    let changes = std::iter::once(Ok(Change::Workspace)).chain(Sway::subscribe()?);
+    for change in changes {
+        match change? {
+            Change::WindowFocus(_) => continue,
+            Change::Window if !skip_empty => continue,
+            _ => (),
+        }
+        let wm_state = WindowManagerState::from_wm(&mut wm)?;
+        let settings = Settings::resolve(opt, config, wm_state.focused_output());
+        skip_empty = settings.skip_empty;
+        let line = line(&wm_state, &settings);
+        if line != last {
+            // Nobody is reading anymore: waybar went away or reloaded
+            if writeln!(io::stdout().lock(), "{}", line).is_err() {
+                return Ok(());
+            }
+            last = line;
+        }
+    }
+    Ok(())
+}
+
+// The focused output's workspaces in the order prev and next go through
+// them: the focused one in brackets, those that they would create in
+// parentheses
+fn line(wm_state: &WindowManagerState, settings: &Settings) -> String {
+    let current = wm_state.current_workspace();
+    let output = wm_state
+        .focused_output()
+        .map(|o| o.name.as_str())
+        .unwrap_or_default();
+    let text = wm_state
+        .workspaces_to_cycle(settings, settings.at_end == AtEnd::Create)
+        .iter()
+        .map(|w| {
+            let label = w.label(output);
+            if w == current {
+                format!("[{}]", label)
+            } else if wm_state.find_workspace(&w.name).is_none() {
+                format!("({})", label)
+            } else {
+                label.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    let towards = |dir| {
+        wm_state
+            .cycle_through_workspaces_on_focused_output(settings, dir)
+            .label(output)
+            .to_string()
+    };
+    json!({
+        "text": text,
+        "tooltip": format!(
+            "{}\nprev: {}\nnext: {}",
+            output,
+            towards(Direction::Prev),
+            towards(Direction::Next)
+        ),
+        "class": if settings.dynamic { "dynamic" } else { "static" },
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::FakeWindowManager;
+    use serde_json::Value;
+    use structopt::StructOpt;
+
+    fn line_for(args: &[&str]) -> Value {
+        let mut wm = FakeWindowManager::default()
+            .with_output("DP-1", 0, 0)
+            .with_output("HDMI-A-1", 1920, 0)
+            .with_workspace("1", "DP-1")
+            .with_workspace("4", "DP-1")
+            .with_workspace("mail", "DP-1")
+            .with_workspace("2", "HDMI-A-1")
+            .showing("2")
+            .focusing("4");
+        let wm_state = WindowManagerState::from_wm(&mut wm).unwrap();
+        let opt = Opt::from_iter(std::iter::once("swayspace").chain(args.iter().copied()));
+        let settings = Settings::resolve(&opt, &Config::default(), wm_state.focused_output());
+        serde_json::from_str(&line(&wm_state, &settings)).unwrap()
+    }
+
+    #[test]
+    fn shows_the_workspaces_of_the_focused_output() {
+        let line = line_for(&["bar"]);
+        assert_eq!(line["text"], "1 [4] mail");
+        assert_eq!(line["tooltip"], "DP-1\nprev: 1\nnext: mail");
+    }
+
+    #[test]
+    fn shows_the_workspaces_that_dynamic_navigation_would_create() {
+        let line = line_for(&["bar", "--dynamic"]);
+        assert_eq!(line["text"], "1 (3) [4] mail (5)");
+        assert_eq!(line["tooltip"], "DP-1\nprev: 3\nnext: mail");
+        assert_eq!(line["class"], "dynamic");
+    }
+}
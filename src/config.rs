@@ -0,0 +1,412 @@
+use crate::backend::Output;
+use crate::error::{Result, SwayspaceError};
+use crate::paths;
+use crate::{AtEnd, Direction, Do, Index, Opt, To};
+use serde::{de, Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+// Contents of `$XDG_CONFIG_HOME/swayspace/config.toml`. Everything is
+// optional: the command line wins over the per-output overrides, which win
+// over the top level settings.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    #[serde(deserialize_with = "parse")]
+    pub command: Option<Do>,
+    #[serde(deserialize_with = "parse")]
+    pub to: Option<To>,
+    #[serde(deserialize_with = "parse")]
+    pub dir: Option<Direction>,
+    pub dynamic: Option<bool>,
+    pub renumber: Option<bool>,
+    pub skip_empty: Option<bool>,
+    pub prefix_output: Option<bool>,
+    pub at_end: AtEndConfig,
+    // Names of the groups that `group` goes through, in order. Each one owns
+    // a workspace per output, see `state::group_workspace`.
+    pub groups: Vec<String>,
+    // Keyed by output name, e.g. `DP-1`, or by "make model serial" like
+    // sway's own output identifiers
+    pub outputs: HashMap<String, OutputConfig>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub dynamic: Option<bool>,
+    pub renumber: Option<bool>,
+    pub skip_empty: Option<bool>,
+    pub prefix_output: Option<bool>,
+    // First and last workspace numbers that belong to this output, e.g.
+    // `[11, 19]`, so that each output keeps its own block of numbers
+    pub range: Option<[i32; 2]>,
+    pub at_end: AtEndConfig,
+}
+
+// What prev and next do past the end, for each target
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AtEndConfig {
+    #[serde(deserialize_with = "parse")]
+    pub workspace: Option<AtEnd>,
+    #[serde(deserialize_with = "parse")]
+    pub output: Option<AtEnd>,
+}
+
+// Where outputs take their numbers from when no output has a range
+const ANY_NUMBER: RangeInclusive<i32> = 1..=i32::MAX;
+
+// Our enums parse themselves the same way on the command line and in the file
+fn parse<'de, D, T>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| s.parse().map_err(de::Error::custom))
+        .transpose()
+}
+
+impl Config {
+    // A missing file is the same as an empty one
+    pub fn load() -> Result<Self> {
+        let path = match paths::config_file() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Self::default()),
+        };
+        std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| Self::parse(&contents))
+            .map_err(|e| SwayspaceError::Config(format!("{:?}: {}", path, e)))
+    }
+    fn parse(contents: &str) -> std::result::Result<Self, String> {
+        let config: Self = toml::from_str(contents).map_err(|e| e.to_string())?;
+        let mut at_ends =
+            std::iter::once(&config.at_end).chain(config.outputs.values().map(|o| &o.at_end));
+        if at_ends.any(|at_end| at_end.output == Some(AtEnd::Create)) {
+            return Err("outputs cannot be created at_end".to_string());
+        }
+        for (i, group) in config.groups.iter().enumerate() {
+            if group.is_empty() || config.groups[..i].contains(group) {
+                return Err(format!("group {:?} is empty or listed twice", group));
+            }
+        }
+        for (output, overrides) in &config.outputs {
+            match overrides.range {
+                Some([first, last]) if first < 1 || last < first => {
+                    return Err(format!("range of {} is empty or not positive", output))
+                }
+                _ => (),
+            }
+        }
+        let mut ranges = config
+            .outputs
+            .iter()
+            .filter_map(|(output, overrides)| Some((overrides.range?, output)))
+            .collect::<Vec<_>>();
+        ranges.sort_unstable();
+        for pair in ranges.windows(2) {
+            if let [([_, last], a), ([first, _], b)] = pair {
+                if first <= last {
+                    return Err(format!("ranges of {} and {} overlap", a, b));
+                }
+            }
+        }
+        Ok(config)
+    }
+    // Numbers that dynamic creation and renumbering use on `output`
+    pub fn range(&self, output: &Output) -> RangeInclusive<i32> {
+        match self.output(output).range {
+            Some([first, last]) => first..=last,
+            None => self.default_range(),
+        }
+    }
+    // Outputs without a range of their own stay out of the others' blocks:
+    // they take the numbers below all of them, or past them all if there is
+    // no room below
+    fn default_range(&self) -> RangeInclusive<i32> {
+        let ranges = self.outputs.values().filter_map(|o| o.range);
+        let first = ranges.clone().map(|[first, _]| first).min();
+        let last = ranges.map(|[_, last]| last).max();
+        match (first, last) {
+            (Some(first), _) if first > 1 => 1..=first - 1,
+            (_, Some(last)) => last.saturating_add(1)..=i32::MAX,
+            _ => ANY_NUMBER,
+        }
+    }
+    // Both entries apply if there are two, field by field. The one by make,
+    // model and serial describes the monitor itself, so it wins.
+    fn output(&self, output: &Output) -> OutputConfig {
+        let by_name = self.outputs.get(&output.name).cloned().unwrap_or_default();
+        match self.outputs.get(&output.identifier()) {
+            Some(by_identifier) => by_identifier.clone().or(by_name),
+            None => by_name,
+        }
+    }
+}
+
+impl OutputConfig {
+    fn or(self, other: Self) -> Self {
+        Self {
+            dynamic: self.dynamic.or(other.dynamic),
+            renumber: self.renumber.or(other.renumber),
+            skip_empty: self.skip_empty.or(other.skip_empty),
+            prefix_output: self.prefix_output.or(other.prefix_output),
+            range: self.range.or(other.range),
+            at_end: self.at_end.or(other.at_end),
+        }
+    }
+}
+
+impl AtEndConfig {
+    fn or(self, other: Self) -> Self {
+        Self {
+            workspace: self.workspace.or(other.workspace),
+            output: self.output.or(other.output),
+        }
+    }
+    // The policy for `to`, if there is one
+    fn get(&self, to: To) -> Option<AtEnd> {
+        match to {
+            To::Workspace => self.workspace,
+            To::Output => self.output,
+            // Only the focused output gets new workspaces
+            To::AllWorkspaces => self.workspace.filter(|&at_end| at_end != AtEnd::Create),
+            To::History | To::Window | To::Scratchpad | To::Group => None,
+        }
+    }
+}
+
+// What to do, once the command line, the config file and the focused output
+// have all had their say
+#[derive(Debug)]
+pub struct Settings {
+    pub command: Do,
+    pub to: To,
+    pub dir: Direction,
+    pub dynamic: bool,
+    pub renumber: bool,
+    pub skip_empty: bool,
+    pub prefix_output: bool,
+    pub at_end: AtEnd,
+    pub range: RangeInclusive<i32>,
+    // Only ever given on the command line: they make no sense as defaults
+    pub index: Option<Index>,
+    pub app_id: Option<String>,
+}
+
+impl Settings {
+    // Flags come in pairs on the command line, like --dynamic and
+    // --no-dynamic, so that it can override the config file either way
+    pub fn resolve(opt: &Opt, config: &Config, focused_output: Option<&Output>) -> Self {
+        let overrides = focused_output.map(|o| config.output(o));
+        let flag = |on: bool,
+                    off: bool,
+                    local: fn(&OutputConfig) -> Option<bool>,
+                    global: Option<bool>| {
+            match (on, off) {
+                (true, _) => true,
+                (_, true) => false,
+                _ => overrides
+                    .as_ref()
+                    .and_then(local)
+                    .or(global)
+                    .unwrap_or(false),
+            }
+        };
+        let to = opt.to.or(config.to).unwrap_or(To::Workspace);
+        let dir = opt.dir.or(config.dir).unwrap_or(Direction::Next);
+        let dynamic = flag(opt.dynamic, opt.no_dynamic, |o| o.dynamic, config.dynamic);
+        // Without a policy, keep to what --dynamic has always done
+        let at_end = opt
+            .at_end
+            .or_else(|| overrides.as_ref().and_then(|o| o.at_end.get(to)))
+            .or_else(|| config.at_end.get(to))
+            .unwrap_or(match (to, dir) {
+                (To::Workspace, _) if dynamic => AtEnd::Create,
+                (To::Output, Direction::Prev | Direction::Next) => AtEnd::Wrap,
+                (To::Output, _) => AtEnd::Stop,
+                _ => AtEnd::Wrap,
+            });
+        Self {
+            command: opt.command.or(config.command).unwrap_or(Do::MoveFocusTo),
+            to,
+            dir,
+            dynamic,
+            renumber: flag(
+                opt.renumber,
+                opt.no_renumber,
+                |o| o.renumber,
+                config.renumber,
+            ),
+            skip_empty: flag(
+                opt.skip_empty,
+                opt.no_skip_empty,
+                |o| o.skip_empty,
+                config.skip_empty,
+            ),
+            prefix_output: flag(
+                opt.prefix_output,
+                opt.no_prefix_output,
+                |o| o.prefix_output,
+                config.prefix_output,
+            ),
+            at_end,
+            range: focused_output.map_or_else(|| config.default_range(), |o| config.range(o)),
+            index: opt.index,
+            app_id: opt.app_id.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use structopt::StructOpt;
+
+    fn output(name: &str) -> Output {
+        Output {
+            x_pos: 0,
+            y_pos: 0,
+            name: name.to_string(),
+            width: 1920,
+            height: 1080,
+            make: "Dell Inc.".to_string(),
+            model: "DELL U2720Q".to_string(),
+            serial: "ABC123".to_string(),
+        }
+    }
+
+    fn resolve(args: &[&str], config: &str, focused_output: &str) -> Settings {
+        let opt = Opt::from_iter(std::iter::once("swayspace").chain(args.iter().copied()));
+        let config = Config::parse(config).unwrap();
+        Settings::resolve(&opt, &config, Some(&output(focused_output)))
+    }
+
+    const CONFIG: &str = r#"
+        to = "output"
+        dir = "prev"
+        dynamic = true
+
+        [outputs.DP-1]
+        dynamic = false
+
+        [outputs.DP-1.at_end]
+        output = "wrap"
+
+        [outputs."Dell Inc. DELL U2720Q ABC123"]
+        renumber = true
+        range = [11, 19]
+    "#;
+
+    #[test]
+    fn defaults_come_from_the_config_file() {
+        let settings = resolve(&[], CONFIG, "HDMI-A-1");
+        assert!(matches!(settings.command, Do::MoveFocusTo));
+        assert!(matches!(settings.to, To::Output));
+        assert!(matches!(settings.dir, Direction::Prev));
+        assert!(settings.dynamic);
+    }
+
+    #[test]
+    fn the_command_line_takes_precedence() {
+        let settings = resolve(
+            &["move-container-to", "workspace", "next"],
+            CONFIG,
+            "HDMI-A-1",
+        );
+        assert!(matches!(settings.command, Do::MoveContainerTo));
+        assert!(matches!(settings.to, To::Workspace));
+        assert!(matches!(settings.dir, Direction::Next));
+        let settings = resolve(&["--dynamic"], CONFIG, "DP-1");
+        assert!(settings.dynamic);
+    }
+
+    #[test]
+    fn outputs_override_the_top_level_by_name() {
+        let settings = resolve(&["move-focus-to", "output", "left"], CONFIG, "DP-1");
+        assert!(!settings.dynamic);
+        assert_eq!(settings.at_end, AtEnd::Wrap);
+        let settings = resolve(&["move-focus-to", "output", "left"], CONFIG, "HDMI-A-1");
+        assert_eq!(settings.at_end, AtEnd::Stop);
+    }
+
+    #[test]
+    fn at_end_is_set_per_target() {
+        let config = "dynamic = true\n[at_end]\nworkspace = \"stop\"\noutput = \"stop\"";
+        assert_eq!(
+            resolve(&["move-focus-to", "workspace"], config, "DP-1").at_end,
+            AtEnd::Stop
+        );
+        assert_eq!(
+            resolve(&["move-focus-to", "output"], config, "DP-1").at_end,
+            AtEnd::Stop
+        );
+        assert_eq!(
+            resolve(&["move-focus-to", "workspace"], "dynamic = true", "DP-1").at_end,
+            AtEnd::Create
+        );
+        assert_eq!(
+            resolve(&["move-focus-to", "output", "next"], "", "DP-1").at_end,
+            AtEnd::Wrap
+        );
+        assert_eq!(
+            resolve(&["move-focus-to", "output", "up"], "", "DP-1").at_end,
+            AtEnd::Stop
+        );
+        let settings = resolve(
+            &["move-focus-to", "workspace", "--at-end", "wrap"],
+            config,
+            "DP-1",
+        );
+        assert_eq!(settings.at_end, AtEnd::Wrap);
+    }
+
+    #[test]
+    fn outputs_can_be_identified_by_make_model_and_serial() {
+        let settings = resolve(&[], CONFIG, "HDMI-A-1");
+        assert!(settings.renumber);
+        assert_eq!(settings.range, 11..=19);
+    }
+
+    #[test]
+    fn entries_by_name_and_by_identifier_both_apply() {
+        let settings = resolve(&[], CONFIG, "DP-1");
+        assert!(!settings.dynamic);
+        assert!(settings.renumber);
+        assert_eq!(settings.range, 11..=19);
+    }
+
+    #[test]
+    fn outputs_without_a_range_stay_out_of_the_others() {
+        let config = "[outputs.DP-1]\nrange = [11, 19]";
+        assert_eq!(resolve(&[], config, "eDP-1").range, 1..=10);
+        let config = "[outputs.DP-1]\nrange = [1, 10]\n[outputs.DP-2]\nrange = [11, 19]";
+        assert_eq!(resolve(&[], config, "eDP-1").range, 20..=i32::MAX);
+        assert_eq!(resolve(&[], "", "eDP-1").range, ANY_NUMBER);
+    }
+
+    #[test]
+    fn the_command_line_can_turn_flags_off() {
+        let settings = resolve(&["--no-dynamic", "--no-renumber"], CONFIG, "HDMI-A-1");
+        assert!(!settings.dynamic);
+        assert!(!settings.renumber);
+        assert!(Opt::from_iter_safe(["swayspace", "--dynamic", "--no-dynamic"]).is_err());
+    }
+
+    #[test]
+    fn typos_are_reported() {
+        assert!(Config::parse("dynamc = true").is_err());
+        assert!(Config::parse("dir = \"sideways\"").is_err());
+        assert!(Config::parse("[outputs.DP-1]\nrange = [9, 1]").is_err());
+        assert!(Config::parse("[at_end]\noutput = \"create\"").is_err());
+        assert!(Config::parse("[outputs.DP-1.at_end]\noutput = \"create\"").is_err());
+        assert!(Config::parse("groups = [\"work\", \"work\"]").is_err());
+        let overlapping = "[outputs.DP-1]\nrange = [1, 10]\n[outputs.DP-2]\nrange = [10, 19]";
+        assert!(Config::parse(overlapping).is_err());
+    }
+}
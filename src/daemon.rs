@@ -0,0 +1,381 @@
+use crate::backend::{Change, CommandBatch, Sway, WindowManager};
+use crate::config::{Config, Settings};
+use crate::error::{Result, SwayspaceError};
+use crate::history::History;
+use crate::homes::Homes;
+use crate::paths;
+use crate::state::WindowManagerState;
+use crate::{execute, renumber, Do, Opt};
+use log::{debug, warn};
+use std::fmt::Display;
+use std::io::{ErrorKind, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use structopt::StructOpt;
+
+pub fn socket_path() -> Result<PathBuf> {
+    paths::runtime_file("swayspace.sock")
+}
+
+fn daemon_error(e: impl Display) -> SwayspaceError {
+    SwayspaceError::Daemon(e.to_string())
+}
+
+// Everything the daemon remembers, behind a single lock so that requests and
+// events never work from each other's stale copies
+struct Shared {
+    state: WindowManagerState,
+    history: History,
+    // Where the last served request is taking focus. Until sway gets there,
+    // focus events are the steps of its batch, not something to remember.
+    settling: Option<String>,
+}
+
+impl Shared {
+    // Whether a workspace change was someone else's doing
+    fn is_external(&mut self, change: &Change) -> bool {
+        let ours = self.settling.is_some();
+        if let Change::WorkspaceFocus(name) = change {
+            if self.settling.as_ref() == Some(name) {
+                self.settling = None;
+            }
+        }
+        !ours
+    }
+}
+
+// Keeps a warm `WindowManagerState` up to date with sway's events,
+// and serves the navigation requests that `forward` sends over the socket.
+// Returns when sway goes away.
+pub fn run(opt: &Opt, config: &Config) -> Result<()> {
+    let shared = Arc::new(Mutex::new(Shared {
+        state: WindowManagerState::from_wm(&mut Sway::connect()?)?,
+        history: History::load(),
+        settling: None,
+    }));
+
+    let listener = listen(&socket_path()?)?;
+    let mut wm = Sway::connect()?;
+    let served = Arc::clone(&shared);
+    let served_config = Arc::new(config.clone());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = serve(stream, &mut wm, &served, &served_config) {
+                        warn!("failed to serve request: {}", e);
+                    }
+                }
+                Err(e) => warn!("failed to accept connection: {}", e),
+            }
+        }
+    });
+
+    let mut wm = Sway::connect()?;
+    let ids = wm.get_workspace_ids()?;
+    let mut homes = Homes::load(&ids);
+    if homes.update(&shared.lock().unwrap().state, &ids) {
+        homes.save();
+    }
+    // After a failed update, only a fresh look at everything can be trusted
+    let mut stale = false;
+    for change in Sway::subscribe()? {
+        let change = change?;
+        debug!("{:?}, updating state", change);
+        match follow(&mut wm, opt, config, &shared, &mut homes, &change, stale) {
+            Ok(()) => stale = false,
+            Err(e) => {
+                warn!("failed to follow {:?}: {}", change, e);
+                stale = true;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Only one daemon at a time: a socket that still answers belongs to a live
+// one. A previous daemon that did not shut down cleanly leaves its socket
+// behind, refusing connections.
+fn listen(path: &Path) -> Result<UnixListener> {
+    match UnixStream::connect(path) {
+        Ok(_) => return Err(daemon_error("already running")),
+        Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+            std::fs::remove_file(path).map_err(daemon_error)?
+        }
+        Err(_) => (),
+    }
+    UnixListener::bind(path).map_err(daemon_error)
+}
+
+// Applies a change to the state, only asking sway for what the event does
+// not say
+fn follow(
+    wm: &mut impl WindowManager,
+    opt: &Opt,
+    config: &Config,
+    shared: &Mutex<Shared>,
+    homes: &mut Homes,
+    change: &Change,
+    stale: bool,
+) -> Result<()> {
+    let workspaces_changed = matches!(change, Change::Workspace | Change::WorkspaceFocus(_));
+    // The focused output may have its own idea of whether to renumber
+    let renumbering = {
+        let shared = shared.lock().unwrap();
+        Settings::resolve(opt, config, shared.state.focused_output()).renumber
+    };
+    if renumbering && workspaces_changed {
+        if let Err(e) = renumber(wm, opt, config) {
+            warn!("failed to renumber workspaces: {}", e);
+        }
+    }
+    let mut shared = shared.lock().unwrap();
+    let external = workspaces_changed && shared.is_external(change);
+    let Shared { state, history, .. } = &mut *shared;
+    match change {
+        _ if stale => *state = WindowManagerState::from_wm(wm)?,
+        Change::WorkspaceFocus(name) if state.focus_workspace(name) => (),
+        Change::Window => state.refresh_windows(wm)?,
+        Change::WindowFocus(id) => state.focus_window(*id),
+        _ => *state = WindowManagerState::from_wm(wm)?,
+    }
+    // Outputs only show through the workspaces sway gives them
+    let plugged = homes.plugged(state);
+    if stale || plugged || *change == Change::Workspace {
+        let ids = wm.get_workspace_ids()?;
+        // Outputs that come back get their workspaces back. Only then: other
+        // moves are on purpose.
+        let restoring = if plugged {
+            homes.restoring(state, &ids)
+        } else {
+            CommandBatch::default()
+        };
+        if !restoring.is_empty() {
+            if let Err(e) = wm.run(&restoring) {
+                warn!("failed to move workspaces back home: {}", e);
+            }
+            *state = WindowManagerState::from_wm(wm)?;
+        }
+        if homes.update(state, &ids) {
+            homes.save();
+        }
+    }
+    // Catches focus changes that did not go through swayspace: `execute`
+    // recorded those that did
+    if external {
+        history.record(&state.current_workspace().name);
+        history.save();
+    }
+    Ok(())
+}
+
+fn serve(
+    mut stream: UnixStream,
+    wm: &mut impl WindowManager,
+    shared: &Mutex<Shared>,
+    config: &Config,
+) -> std::io::Result<()> {
+    let mut request = String::new();
+    stream.read_to_string(&mut request)?;
+    let mut shared = shared.lock().unwrap();
+    let Shared {
+        state,
+        history,
+        settling,
+    } = &mut *shared;
+    let before = state.current_workspace().name.clone();
+    let result = handle(wm, state, config, history, &request);
+    if result.is_ok() {
+        *settling = history
+            .current()
+            .filter(|&w| w != before)
+            .map(str::to_string);
+    }
+    // Still under the lock: the file is for when the daemon is not running
+    history.save();
+    let reply = match result {
+        Ok(()) => "0".to_string(),
+        Err(e) => format!("{} {}", e.exit_code(), e),
+    };
+    stream.write_all(reply.as_bytes())
+}
+
+// Requests are the client's command line arguments, separated by NUL bytes
+fn handle(
+    wm: &mut impl WindowManager,
+    state: &mut WindowManagerState,
+    config: &Config,
+    history: &mut History,
+    request: &str,
+) -> Result<()> {
+    let args = std::iter::once("swayspace").chain(request.split('\0').filter(|a| !a.is_empty()));
+    let opt = Opt::from_iter_safe(args).map_err(|e| daemon_error(e.message))?;
+    let settings = Settings::resolve(&opt, config, state.focused_output());
+    match settings.command {
+        Do::Daemon => return Err(daemon_error("already running")),
+        Do::State | Do::Bar => return Err(daemon_error("reports are printed by the client")),
+        _ => (),
+    }
+    execute(wm, state, &settings, config, history)?;
+    if settings.renumber {
+        renumber(wm, &opt, config)?;
+    }
+    Ok(())
+}
+
+// Hands this invocation over to a running daemon. Returns None if there is no
+// daemon listening, in which case the caller should talk to sway itself.
+pub fn forward(args: &[String]) -> Option<Result<()>> {
+    let stream = UnixStream::connect(socket_path().ok()?).ok()?;
+    Some(send(stream, args))
+}
+
+fn send(mut stream: UnixStream, args: &[String]) -> Result<()> {
+    stream
+        .write_all(args.join("\0").as_bytes())
+        .and_then(|_| stream.shutdown(Shutdown::Write))
+        .map_err(daemon_error)?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).map_err(daemon_error)?;
+    match reply.split_once(' ') {
+        None if reply == "0" => Ok(()),
+        Some((code, message)) => Err(SwayspaceError::Remote {
+            code: code.parse().map_err(daemon_error)?,
+            message: message.to_string(),
+        }),
+        None => Err(daemon_error(format!("unexpected reply {:?}", reply))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::FakeWindowManager;
+
+    fn two_workspaces() -> FakeWindowManager {
+        FakeWindowManager::default()
+            .with_output("DP-1", 0, 0)
+            .with_workspace("1", "DP-1")
+            .with_workspace("2", "DP-1")
+            .focusing("1")
+    }
+
+    #[test]
+    fn forwarded_requests_are_run_against_the_warm_state() {
+        let mut wm = two_workspaces();
+        let mut state = WindowManagerState::from_wm(&mut wm).unwrap();
+        handle(
+            &mut wm,
+            &mut state,
+            &Config::default(),
+            &mut History::default(),
+            "move-focus-to\0workspace\0next",
+        )
+        .unwrap();
+        assert_eq!(wm.commands, vec!["workspace number 2"]);
+    }
+
+    // What `state` prints: focus, and each output with its workspaces
+    fn outputs(state: &WindowManagerState) -> serde_json::Value {
+        serde_json::json!([state.current_workspace(), state.outputs()])
+    }
+
+    #[test]
+    fn focus_changes_are_followed_without_asking_sway() {
+        let wm = two_workspaces().with_windows("2", 1);
+        let mut state = WindowManagerState::from_wm(&mut wm.clone()).unwrap();
+        assert!(state.focus_workspace("2"));
+        let mut focused = wm.focusing("2");
+        assert_eq!(
+            outputs(&state),
+            outputs(&WindowManagerState::from_wm(&mut focused).unwrap())
+        );
+        assert!(!state.focus_workspace("9"));
+    }
+
+    #[test]
+    fn focus_changes_to_other_outputs_are_followed_too() {
+        let mut wm = two_workspaces()
+            .with_output("HDMI-A-1", 1920, 0)
+            .with_workspace("3", "HDMI-A-1")
+            .showing("3");
+        let mut state = WindowManagerState::from_wm(&mut wm).unwrap();
+        assert!(state.focus_workspace("3"));
+        assert_eq!(state.focused_output().unwrap().name, "HDMI-A-1");
+        assert_eq!(state.current_workspace().name, "3");
+        assert!(state.focus_workspace("1"));
+        assert_eq!(state.focused_output().unwrap().name, "DP-1");
+    }
+
+    #[test]
+    fn window_changes_only_recount_windows() {
+        let mut wm = two_workspaces();
+        let mut state = WindowManagerState::from_wm(&mut wm).unwrap();
+        let mut wm = wm.with_windows("1", 2);
+        state.refresh_windows(&mut wm).unwrap();
+        assert_eq!(state.current_workspace().windows, 2);
+        assert_eq!(
+            outputs(&state),
+            outputs(&WindowManagerState::from_wm(&mut wm).unwrap())
+        );
+    }
+
+    #[test]
+    fn the_steps_of_a_served_batch_are_not_recorded() {
+        let mut wm = two_workspaces().with_workspace("3", "DP-1");
+        let mut shared = Shared {
+            state: WindowManagerState::from_wm(&mut wm).unwrap(),
+            history: History::default(),
+            settling: Some("3".to_string()),
+        };
+        assert!(!shared.is_external(&Change::WorkspaceFocus("2".to_string())));
+        assert!(!shared.is_external(&Change::Workspace));
+        assert!(!shared.is_external(&Change::WorkspaceFocus("3".to_string())));
+        assert!(shared.is_external(&Change::WorkspaceFocus("2".to_string())));
+    }
+
+    #[test]
+    fn the_daemon_does_not_start_another_daemon() {
+        let mut wm = two_workspaces();
+        let mut state = WindowManagerState::from_wm(&mut wm).unwrap();
+        let error = handle(
+            &mut wm,
+            &mut state,
+            &Config::default(),
+            &mut History::default(),
+            "daemon",
+        )
+        .unwrap_err();
+        assert_eq!(error.exit_code(), 7);
+        assert!(wm.commands.is_empty());
+    }
+
+    #[test]
+    fn a_live_socket_is_left_alone_and_a_stale_one_replaced() {
+        let path = std::env::temp_dir().join(format!("swayspace-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let live = listen(&path).unwrap();
+        assert_eq!(listen(&path).unwrap_err().exit_code(), 7);
+        drop(live);
+        listen(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn errors_keep_their_exit_code_across_the_socket() {
+        let (client, mut server) = UnixStream::pair().unwrap();
+        thread::spawn(move || {
+            let mut request = String::new();
+            server.read_to_string(&mut request).unwrap();
+            assert_eq!(request, "move-focus-to\0output");
+            server.write_all(b"6 sway rejected it").unwrap();
+        });
+        let args = vec!["move-focus-to".to_string(), "output".to_string()];
+        let error = send(client, &args).unwrap_err();
+        assert_eq!(error.exit_code(), 6);
+        assert_eq!(error.to_string(), "sway rejected it");
+    }
+}
@@ -0,0 +1,64 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum SwayspaceError {
+    // Could not connect to sway's IPC socket at all
+    IpcUnavailable(String),
+    // Connected, but a request to sway failed
+    Ipc(String),
+    NoFocusedOutput,
+    NoFocusedWorkspace,
+    CommandRejected { command: String, reason: String },
+    // Talking to the daemon over its socket failed
+    Daemon(String),
+    // The daemon ran the request for us, and it failed with this exit code
+    Remote { code: i32, message: String },
+    // The combination of arguments makes no sense, e.g. toggling an output
+    Unsupported(String),
+    // The config file exists but could not be read or parsed
+    Config(String),
+    // XDG_RUNTIME_DIR is not set: there is nowhere private to keep the socket
+    NoRuntimeDir,
+}
+
+pub type Result<T> = std::result::Result<T, SwayspaceError>;
+
+impl SwayspaceError {
+    // Distinct per variant so that scripts can tell failures apart. 1 is left
+    // to clap for command line parsing errors.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::IpcUnavailable(_) => 2,
+            Self::Ipc(_) => 3,
+            Self::NoFocusedOutput => 4,
+            Self::NoFocusedWorkspace => 5,
+            Self::CommandRejected { .. } => 6,
+            Self::Daemon(_) => 7,
+            Self::Remote { code, .. } => *code,
+            Self::Unsupported(_) => 8,
+            Self::Config(_) => 9,
+            Self::NoRuntimeDir => 10,
+        }
+    }
+}
+
+impl fmt::Display for SwayspaceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::IpcUnavailable(e) => write!(f, "could not connect to sway: {}", e),
+            Self::Ipc(e) => write!(f, "sway IPC request failed: {}", e),
+            Self::NoFocusedOutput => write!(f, "no output is focused"),
+            Self::NoFocusedWorkspace => write!(f, "no workspace is focused"),
+            Self::CommandRejected { command, reason } => {
+                write!(f, "sway rejected `{}`: {}", command, reason)
+            }
+            Self::Daemon(e) => write!(f, "daemon: {}", e),
+            Self::Remote { message, .. } => write!(f, "{}", message),
+            Self::Unsupported(e) => write!(f, "{}", e),
+            Self::Config(e) => write!(f, "invalid config file {}", e),
+            Self::NoRuntimeDir => write!(f, "XDG_RUNTIME_DIR is not set"),
+        }
+    }
+}
+
+impl std::error::Error for SwayspaceError {}
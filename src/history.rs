@@ -0,0 +1,161 @@
+use crate::error::Result;
+use crate::paths;
+use log::warn;
+use std::path::PathBuf;
+
+const MAX_ENTRIES: usize = 100;
+
+// Names of the workspaces focused over time, oldest first, like a browser's
+// history: going back and forth moves the cursor, focusing anything else
+// drops whatever was ahead of it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct History {
+    entries: Vec<String>,
+    cursor: usize,
+}
+
+impl History {
+    fn path() -> Result<PathBuf> {
+        paths::runtime_file("swayspace-history")
+    }
+    // A missing or unreadable file is just an empty history
+    pub fn load() -> Self {
+        Self::path()
+            .ok()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|contents| Self::parse(&contents))
+            .unwrap_or_default()
+    }
+    // History is a convenience: failing to save it should not fail navigation
+    pub fn save(&self) {
+        let path = match Self::path() {
+            Ok(path) => path,
+            Err(e) => {
+                warn!("failed to save history: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = std::fs::write(&path, self.to_string()) {
+            warn!("failed to save history to {:?}: {}", path, e);
+        }
+    }
+    // First line is the cursor, then one workspace name per line
+    fn parse(contents: &str) -> Self {
+        let mut lines = contents.lines();
+        let cursor = lines.next().and_then(|l| l.parse().ok()).unwrap_or(0);
+        let entries = lines.map(str::to_string).collect::<Vec<_>>();
+        Self {
+            cursor: cursor.min(entries.len().saturating_sub(1)),
+            entries,
+        }
+    }
+    pub fn record(&mut self, workspace: &str) {
+        if self.entries.get(self.cursor).map(String::as_str) == Some(workspace) {
+            return;
+        }
+        if !self.entries.is_empty() {
+            self.entries.truncate(self.cursor + 1);
+        }
+        self.entries.push(workspace.to_string());
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+        self.cursor = self.entries.len() - 1;
+    }
+    pub fn current(&self) -> Option<&str> {
+        self.entries.get(self.cursor).map(String::as_str)
+    }
+    // Workspaces that were destroyed since they were recorded are skipped
+    pub fn back(&mut self, exists: impl Fn(&str) -> bool) -> Option<String> {
+        let position = (0..self.cursor).rev().find(|&i| exists(&self.entries[i]))?;
+        self.cursor = position;
+        Some(self.entries[position].clone())
+    }
+    pub fn forward(&mut self, exists: impl Fn(&str) -> bool) -> Option<String> {
+        let position = (self.cursor + 1..self.entries.len()).find(|&i| exists(&self.entries[i]))?;
+        self.cursor = position;
+        Some(self.entries[position].clone())
+    }
+    // The most recent other workspace, alt-tab style. Unlike `back`, this
+    // counts as focusing something new once recorded, so toggling twice
+    // returns to where we started.
+    pub fn toggle(&self, exists: impl Fn(&str) -> bool) -> Option<String> {
+        let current = self.entries.get(self.cursor)?;
+        self.entries[..self.cursor]
+            .iter()
+            .rev()
+            .find(|&w| w != current && exists(w))
+            .cloned()
+    }
+}
+
+impl std::fmt::Display for History {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "{}", self.cursor)?;
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn visited(workspaces: &[&str]) -> History {
+        let mut history = History::default();
+        for w in workspaces {
+            history.record(w);
+        }
+        history
+    }
+
+    fn all(_: &str) -> bool {
+        true
+    }
+
+    #[test]
+    fn back_and_forward_move_through_visited_workspaces() {
+        let mut history = visited(&["1", "2", "3"]);
+        assert_eq!(history.back(all), Some("2".to_string()));
+        assert_eq!(history.back(all), Some("1".to_string()));
+        assert_eq!(history.back(all), None);
+        assert_eq!(history.forward(all), Some("2".to_string()));
+        history.record("2");
+        assert_eq!(history.forward(all), Some("3".to_string()));
+        assert_eq!(history.forward(all), None);
+    }
+
+    #[test]
+    fn focusing_something_new_drops_what_was_ahead() {
+        let mut history = visited(&["1", "2", "3"]);
+        history.back(all);
+        history.record("mail");
+        assert_eq!(history.forward(all), None);
+        assert_eq!(history.back(all), Some("2".to_string()));
+    }
+
+    #[test]
+    fn toggle_alternates_between_the_two_most_recent_workspaces() {
+        let mut history = visited(&["1", "2", "3"]);
+        let previous = history.toggle(all).unwrap();
+        assert_eq!(previous, "2");
+        history.record(&previous);
+        let previous = history.toggle(all).unwrap();
+        assert_eq!(previous, "3");
+    }
+
+    #[test]
+    fn destroyed_workspaces_are_skipped() {
+        let mut history = visited(&["1", "2", "3"]);
+        assert_eq!(history.back(|w| w != "2"), Some("1".to_string()));
+    }
+
+    #[test]
+    fn survives_a_round_trip_through_the_state_file() {
+        let mut history = visited(&["1", "3:web", "mail"]);
+        history.back(all);
+        assert_eq!(History::parse(&history.to_string()), history);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::backend::CommandBatch;
+use crate::error::Result;
+use crate::paths;
+use crate::state::{quote, WindowManagerState};
+use log::warn;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+
+// The output each workspace belongs on, by the output's make, model and
+// serial since connector names change from one plug to the next. Workspaces
+// go by their id, which survives renumbering. When an output goes away, sway
+// piles its workspaces onto the remaining ones: their home is kept until it
+// comes back, so that they can be sent back there.
+#[derive(Debug, Default)]
+pub struct Homes {
+    homes: BTreeMap<i64, Home>,
+    // Outputs as of the last update, to notice those that come back
+    connected: HashSet<String>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Home {
+    // Name of the workspace when we last saw it
+    workspace: String,
+    output: String,
+}
+
+impl Homes {
+    fn path() -> Result<PathBuf> {
+        paths::runtime_file("swayspace-homes")
+    }
+    // A missing or unreadable file is just no homes yet
+    pub fn load(ids: &HashMap<String, i64>) -> Self {
+        Self::path()
+            .ok()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|contents| Self::parse(&contents, ids))
+            .unwrap_or_default()
+    }
+    pub fn save(&self) {
+        let path = match Self::path() {
+            Ok(path) => path,
+            Err(e) => {
+                warn!("failed to save workspace homes: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = std::fs::write(&path, self.to_string()) {
+            warn!("failed to save workspace homes to {:?}: {}", path, e);
+        }
+    }
+    // One workspace per line: its id, the identifier of its output and its
+    // name, separated by tabs. Sway hands ids out again when it restarts, so
+    // one that now belongs to a workspace of another name is forgotten.
+    fn parse(contents: &str, ids: &HashMap<String, i64>) -> Self {
+        Self {
+            homes: contents
+                .lines()
+                .filter_map(|line| {
+                    let mut fields = line.splitn(3, '\t');
+                    let id = fields.next()?.parse().ok()?;
+                    let output = fields.next()?.to_string();
+                    let workspace = fields.next()?.to_string();
+                    Some((id, Home { workspace, output }))
+                })
+                .filter(|(id, home)| {
+                    ids.iter()
+                        .all(|(name, other)| other != id || *name == home.workspace)
+                })
+                .collect(),
+            connected: HashSet::new(),
+        }
+    }
+    // Workspaces move in with the output they are on, unless their home is
+    // disconnected: then they are only staying there until it returns.
+    // Destroyed workspaces are forgotten once their home is back. Returns
+    // whether anything changed.
+    pub fn update(&mut self, wm_state: &WindowManagerState, ids: &HashMap<String, i64>) -> bool {
+        let connected = Self::connected(wm_state);
+        let live = ids.values().collect::<HashSet<_>>();
+        let before = self.homes.len();
+        self.homes
+            .retain(|id, home| live.contains(id) || !connected.contains_key(&home.output));
+        let mut changed = self.homes.len() != before;
+        self.connected = connected.keys().cloned().collect();
+        for o in wm_state.outputs() {
+            let identifier = o.output.identifier();
+            for w in o.workspaces {
+                let id = match ids.get(&w.name) {
+                    Some(&id) => id,
+                    None => continue,
+                };
+                let home = self.homes.entry(id).or_insert_with(|| Home {
+                    workspace: w.name.clone(),
+                    output: String::new(),
+                });
+                if home.workspace != w.name {
+                    home.workspace = w.name.clone();
+                    changed = true;
+                }
+                let away = !home.output.is_empty() && !connected.contains_key(&home.output);
+                if home.output != identifier && !away {
+                    home.output = identifier.clone();
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+    // Whether an output was connected since the last update
+    pub fn plugged(&self, wm_state: &WindowManagerState) -> bool {
+        Self::connected(wm_state)
+            .keys()
+            .any(|identifier| !self.connected.contains(identifier))
+    }
+    // Sends the workspaces whose home is connected again back there, then
+    // returns focus to the workspace that had it
+    pub fn restoring(
+        &self,
+        wm_state: &WindowManagerState,
+        ids: &HashMap<String, i64>,
+    ) -> CommandBatch {
+        let connected = Self::connected(wm_state);
+        let batch = wm_state
+            .outputs()
+            .into_iter()
+            .flat_map(|o| {
+                let identifier = o.output.identifier();
+                o.workspaces
+                    .into_iter()
+                    .map(move |w| (w, identifier.clone()))
+            })
+            .filter_map(|(w, identifier)| {
+                let home = self.homes.get(ids.get(&w.name)?)?;
+                if home.output == identifier {
+                    return None;
+                }
+                Some((w, connected.get(&home.output)?.to_string()))
+            })
+            .fold(CommandBatch::default(), |batch, (w, output)| {
+                batch
+                    .then(format!("workspace {}", w.as_arg()))
+                    .then(format!("move workspace to output {}", quote(&output)))
+            });
+        if batch.is_empty() {
+            return batch;
+        }
+        batch.then(format!(
+            "workspace {}",
+            wm_state.current_workspace().as_arg()
+        ))
+    }
+    // Connector names by identifier
+    fn connected(wm_state: &WindowManagerState) -> HashMap<String, String> {
+        wm_state
+            .outputs()
+            .into_iter()
+            .map(|o| (o.output.identifier(), o.output.name.clone()))
+            .collect()
+    }
+}
+
+impl std::fmt::Display for Homes {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (id, home) in &self.homes {
+            writeln!(f, "{}\t{}\t{}", id, home.output, home.workspace)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{FakeWindowManager, WindowManager};
+
+    // DP-1 (focused): 1, 2 | HDMI-A-1: 3
+    fn two_outputs() -> FakeWindowManager {
+        FakeWindowManager::default()
+            .with_output("DP-1", 0, 0)
+            .with_output("HDMI-A-1", 1920, 0)
+            .with_workspace("1", "DP-1")
+            .with_workspace("2", "DP-1")
+            .with_workspace("3", "HDMI-A-1")
+            .showing("3")
+            .focusing("1")
+    }
+
+    // HDMI-A-1 was unplugged: sway moved 3 over to DP-1
+    fn unplugged() -> FakeWindowManager {
+        FakeWindowManager::default()
+            .with_output("DP-1", 0, 0)
+            .with_workspace("1", "DP-1")
+            .with_workspace("2", "DP-1")
+            .with_workspace("3", "DP-1")
+            .focusing("1")
+    }
+
+    fn homes_in(mut wm: FakeWindowManager, homes: &mut Homes) -> bool {
+        let ids = wm.get_workspace_ids().unwrap();
+        homes.update(&WindowManagerState::from_wm(&mut wm).unwrap(), &ids)
+    }
+
+    fn restoring(mut wm: FakeWindowManager, homes: &Homes) -> String {
+        let ids = wm.get_workspace_ids().unwrap();
+        homes
+            .restoring(&WindowManagerState::from_wm(&mut wm).unwrap(), &ids)
+            .to_string()
+    }
+
+    // HDMI-A-1 is back, now plugged in as HDMI-A-2
+    fn replugged(renamed: &str) -> FakeWindowManager {
+        let mut wm = FakeWindowManager::default()
+            .with_output("DP-1", 0, 0)
+            .with_output("HDMI-A-2", 1920, 0)
+            .with_workspace("1", "DP-1")
+            .with_workspace("2", "DP-1")
+            .with_workspace(renamed, "DP-1")
+            .with_workspace("4", "HDMI-A-2")
+            .showing("4")
+            .focusing("1");
+        wm.outputs[1].model = "HDMI-A-1".to_string();
+        wm
+    }
+
+    #[test]
+    fn workspaces_are_sent_back_home_when_their_output_returns() {
+        let mut homes = Homes::default();
+        assert!(homes_in(two_outputs(), &mut homes));
+        assert!(!homes_in(unplugged(), &mut homes));
+        assert_eq!(restoring(unplugged(), &homes), "");
+        let mut replugged = replugged("3");
+        assert!(homes.plugged(&WindowManagerState::from_wm(&mut replugged).unwrap()));
+        assert_eq!(
+            restoring(replugged, &homes),
+            "workspace number 3; move workspace to output \"HDMI-A-2\"; workspace number 1"
+        );
+    }
+
+    #[test]
+    fn workspaces_renamed_while_away_still_go_home() {
+        let mut homes = Homes::default();
+        homes_in(two_outputs(), &mut homes);
+        let mut renamed = unplugged();
+        renamed.workspaces[2].name = "3:web".to_string();
+        assert!(homes_in(renamed, &mut homes));
+        assert_eq!(
+            restoring(replugged("3:web"), &homes),
+            "workspace \"3:web\"; move workspace to output \"HDMI-A-2\"; workspace number 1"
+        );
+    }
+
+    #[test]
+    fn workspaces_moved_on_purpose_change_home() {
+        let mut homes = Homes::default();
+        homes_in(two_outputs(), &mut homes);
+        let moved = FakeWindowManager::default()
+            .with_output("DP-1", 0, 0)
+            .with_output("HDMI-A-1", 1920, 0)
+            .with_workspace("1", "DP-1")
+            .with_workspace("2", "HDMI-A-1")
+            .with_workspace("3", "HDMI-A-1")
+            .showing("3")
+            .focusing("1");
+        assert!(homes_in(moved, &mut homes));
+        assert_eq!(
+            restoring(two_outputs(), &homes),
+            "workspace number 2; move workspace to output \"HDMI-A-1\"; workspace number 1"
+        );
+    }
+
+    #[test]
+    fn survives_a_round_trip_through_the_state_file() {
+        let mut wm = two_outputs().with_workspace("3:web", "DP-1");
+        let mut homes = Homes::default();
+        homes_in(wm.clone(), &mut homes);
+        let ids = wm.get_workspace_ids().unwrap();
+        assert_eq!(Homes::parse(&homes.to_string(), &ids).homes, homes.homes);
+        // After sway restarted, 2 has the id that 3:web had
+        let ids = HashMap::from([("2".to_string(), 4)]);
+        assert_eq!(Homes::parse(&homes.to_string(), &ids).homes.len(), 3);
+    }
+}
@@ -1,15 +1,36 @@
 #![feature(iter_partition_in_place)]
 
+mod backend;
+mod bar;
+mod config;
+mod daemon;
+mod error;
+mod history;
+mod homes;
+mod paths;
+mod state;
+
+use backend::{CommandBatch, Output, Sway, WindowManager, SCRATCHPAD, SHOWN};
 use clap::arg_enum;
+use config::{Config, Settings};
+use error::SwayspaceError;
+use history::History;
+use serde::Serialize;
+use serde_json::json;
+use state::{group_workspace, quote, WindowManagerState, Workspace};
 use std::str::FromStr;
 use structopt::StructOpt;
-use swayipc::Connection;
 
 arg_enum! {
     #[derive(Debug, Clone, Copy)]
 enum To {
     Workspace,
     Output,
+    History,
+    AllWorkspaces,
+    Window,
+    Scratchpad,
+    Group,
 }
 }
 
@@ -18,13 +39,34 @@ arg_enum! {
 enum Direction {
     Prev,
     Next,
+    Toggle,
+    Left,
+    Right,
+    Up,
+    Down,
+}
+}
+
+arg_enum! {
+    // What prev and next do past the last workspace or output
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AtEnd {
+    Wrap,
+    Stop,
+    Create,
 }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 enum Do {
     MoveFocusTo,
     MoveContainerTo,
+    SendContainerTo,
+    MoveWorkspaceTo,
+    Swap,
+    State,
+    Bar,
+    Daemon,
 }
 
 impl FromStr for Do {
@@ -33,184 +75,1571 @@ impl FromStr for Do {
         match s {
             "move-focus-to" => Ok(Self::MoveFocusTo),
             "move-container-to" => Ok(Self::MoveContainerTo),
+            "send-container-to" => Ok(Self::SendContainerTo),
+            "move-workspace-to" => Ok(Self::MoveWorkspaceTo),
+            "swap" => Ok(Self::Swap),
+            "state" => Ok(Self::State),
+            "bar" => Ok(Self::Bar),
+            "daemon" => Ok(Self::Daemon),
             _ => Err(format!(
-                "Failed to parse {} as --do. Expected one of [move-focus-to, move-container-to]",
+                "Failed to parse {} as --do. Expected one of [move-focus-to, move-container-to, send-container-to, move-workspace-to, swap, state, bar, daemon]",
                 s
             )),
         }
     }
 }
 
+// A position among the workspaces of the focused output, counting from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Index {
+    First,
+    Last,
+    Nth(usize),
+}
+
+impl FromStr for Index {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first" => Ok(Self::First),
+            "last" => Ok(Self::Last),
+            _ => match s.parse() {
+                Ok(n) if n > 0 => Ok(Self::Nth(n)),
+                _ => Err(format!(
+                    "Failed to parse {} as --index. Expected first, last or a number from 1",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+// Positional arguments and flags left out here fall back to
+// $XDG_CONFIG_HOME/swayspace/config.toml, see `config::Settings`
 #[derive(Debug, StructOpt)]
 #[structopt(about = "Automatically create workspaces under sway like gnome does")]
 struct Opt {
-    #[structopt(default_value = "move-focus-to", possible_values = &["move-focus-to", "move-container-to"])]
-    command: Do,
-    #[structopt(default_value = "workspace", possible_values = &To::variants(), case_insensitive = true)]
-    to: To,
-    #[structopt(default_value = "next", possible_values = &Direction::variants(), case_insensitive = true, help = "Direction to move towards")]
-    dir: Direction,
+    #[structopt(
+        possible_values = &[
+            "move-focus-to",
+            "move-container-to",
+            "send-container-to",
+            "move-workspace-to",
+            "swap",
+            "state",
+            "bar",
+            "daemon",
+        ],
+        help = "`send-container-to` moves the focused window like `move-container-to` but leaves focus where it is. `move-workspace-to` takes the focused workspace along to another output, renumbering it if its number belongs elsewhere. `swap` exchanges the workspaces shown on the focused output and the target one. `state` prints the outputs and workspaces as swayspace sees them. `bar` keeps printing the workspaces of the focused output for a waybar custom module. `daemon` keeps the state warm in the background and serves the other commands over a unix socket, sending workspaces back to their output when it is plugged back in [default: move-focus-to]"
+    )]
+    command: Option<Do>,
+    #[structopt(possible_values = &To::variants(), case_insensitive = true, help = "AllWorkspaces goes through the workspaces of every output, from left to right. Window goes through their windows, tiled before floating ones on each workspace. Scratchpad stashes containers there, and shows its hidden windows in turn, putting the one it showed before back first. Group switches every output to its workspace of the next group listed in the config file [default: workspace]")]
+    to: Option<To>,
+    #[structopt(possible_values = &Direction::variants(), case_insensitive = true, help = "Direction to move towards. Through history, prev and next step back and forward while toggle goes to the most recently used workspace [default: next]")]
+    dir: Option<Direction>,
     #[structopt(
         long = "dynamic",
         help = "Used when cycling between workspaces: If the next available workspace does not exist, create it."
     )]
     dynamic: bool,
+    #[structopt(
+        long = "no-dynamic",
+        conflicts_with = "dynamic",
+        help = "Never create workspaces, whatever the config file says."
+    )]
+    no_dynamic: bool,
+    #[structopt(
+        long = "renumber",
+        help = "Close the gaps left by destroyed workspaces by renumbering the ones on the focused output. Together with --dynamic, this keeps a compact list with a single empty workspace at its end, like gnome does. With daemon, this happens on every workspace change."
+    )]
+    renumber: bool,
+    #[structopt(
+        long = "no-renumber",
+        conflicts_with = "renumber",
+        help = "Leave workspace numbers alone, whatever the config file says."
+    )]
+    no_renumber: bool,
+    #[structopt(
+        long = "skip-empty",
+        help = "Used when cycling between workspaces: Go past those without any windows. New ones are still created at the end with --dynamic."
+    )]
+    skip_empty: bool,
+    #[structopt(
+        long = "no-skip-empty",
+        conflicts_with = "skip_empty",
+        help = "Go through empty workspaces too, whatever the config file says."
+    )]
+    no_skip_empty: bool,
+    #[structopt(
+        long = "prefix-output",
+        help = "Used when creating workspaces: Name them after the focused output too, like 1:DP-1, so that each output counts from the start of its range without skipping numbers used elsewhere. The prefix is left out when printing workspaces."
+    )]
+    prefix_output: bool,
+    #[structopt(
+        long = "no-prefix-output",
+        conflicts_with = "prefix_output",
+        help = "Name new workspaces by number alone, whatever the config file says."
+    )]
+    no_prefix_output: bool,
+    #[structopt(
+        long = "at-end",
+        possible_values = &AtEnd::variants(),
+        case_insensitive = true,
+        help = "What to do past the last workspace or output: wrap around to the first one, stop there, or create a new workspace. Defaults to create for workspaces with --dynamic, stop for outputs left, right, up or down, and wrap otherwise. Wrapping between outputs left, right, up or down goes to the farthest one on the other side."
+    )]
+    at_end: Option<AtEnd>,
+    #[structopt(
+        long = "index",
+        help = "Go to the workspace at this position on the focused output instead, in the order that prev and next go through them: first, last or a number from 1. With --dynamic, gaps count as workspaces and positions past the end create new ones."
+    )]
+    index: Option<Index>,
+    #[structopt(
+        long = "app-id",
+        help = "Used when cycling between windows: Only go through those of this application."
+    )]
+    app_id: Option<String>,
+    #[structopt(
+        long = "dry-run",
+        help = "Print swayspace's view of sway, the commands it would send and where focus would end up, without sending them. Renumbering is left out: it depends on the state after navigating."
+    )]
+    dry_run: bool,
+    #[structopt(long = "json", help = "Used with state and --dry-run: print as JSON.")]
+    json: bool,
 }
 
-struct WindowManagerState {
-    current_workspace: i32,
-    workspaces_on_focused_output: Vec<i32>,
-    workspaces_on_unfocused_outputs: Vec<i32>,
-    max_workspace_on_focused_output: i32,
-    // For each output in order of its x position, the num of its visible workspace
-    visible_workspace_per_output: Vec<i32>,
+fn pick_destination(
+    wm_state: &WindowManagerState,
+    settings: &Settings,
+    history: &mut History,
+) -> error::Result<Workspace> {
+    match (settings.to, settings.index) {
+        (To::Workspace, Some(index)) => {
+            return Ok(wm_state.nth_workspace_on_focused_output(index, settings))
+        }
+        (to, Some(_)) => {
+            return Err(SwayspaceError::Unsupported(format!(
+                "--index does not apply to {:?}",
+                to
+            )))
+        }
+        _ => (),
+    }
+    match (settings.to, settings.dir) {
+        (To::History, dir @ (Direction::Prev | Direction::Next | Direction::Toggle)) => {
+            let exists = |name: &str| wm_state.find_workspace(name).is_some();
+            let name = match dir {
+                Direction::Prev => history.back(exists),
+                Direction::Next => history.forward(exists),
+                _ => history.toggle(exists),
+            };
+            Ok(name
+                .and_then(|name| wm_state.find_workspace(&name))
+                .unwrap_or_else(|| wm_state.current_workspace())
+                .clone())
+        }
+        (To::Workspace, dir @ (Direction::Prev | Direction::Next)) => {
+            Ok(wm_state.cycle_through_workspaces_on_focused_output(settings, dir))
+        }
+        (To::AllWorkspaces, dir @ (Direction::Prev | Direction::Next)) => {
+            Ok(wm_state.cycle_through_all_workspaces(settings, dir))
+        }
+        (To::Output, dir @ (Direction::Prev | Direction::Next)) => {
+            Ok(wm_state.cycle_through_outputs(dir, settings.at_end))
+        }
+        (
+            To::Output,
+            dir @ (Direction::Left | Direction::Right | Direction::Up | Direction::Down),
+        ) => Ok(wm_state.adjacent_output(dir, settings.at_end == AtEnd::Wrap)),
+        (to, dir) => Err(SwayspaceError::Unsupported(format!(
+            "{:?} does not apply to {:?}",
+            dir, to
+        ))),
+    }
 }
 
-#[derive(PartialEq, Eq, Ord, PartialOrd)]
-struct Output {
-    x_pos: i64,
-    y_pos: i64,
-    name: String,
+fn commands(destination: &Workspace, settings: &Settings) -> CommandBatch {
+    let batch = CommandBatch::default();
+    match settings.command {
+        Do::MoveFocusTo => batch.then(format!("workspace {}", destination.as_arg())),
+        Do::MoveContainerTo => batch
+            .then(format!(
+                "move container to workspace {}",
+                destination.as_arg()
+            ))
+            .then(format!("workspace {}", destination.as_arg())),
+        Do::SendContainerTo => batch.then(format!(
+            "move container to workspace {}",
+            destination.as_arg()
+        )),
+        // Not navigations: see `plan`, `run`, and the bar and daemon modules
+        Do::MoveWorkspaceTo | Do::Swap | Do::State | Do::Bar | Do::Daemon => batch,
+    }
 }
 
-impl WindowManagerState {
-    fn from_wm(wm: &mut Connection) -> Self {
-        let focused_output_name = wm
-            .get_tree()
-            .unwrap()
-            .find_focused(|node| match node.node_type {
-                swayipc::reply::NodeType::Output => true,
-                _ => false,
-            })
-            .unwrap()
-            .name
-            .unwrap();
+// The output showing `destination` when commands that act on whole outputs
+// apply, or None if there is no other output that way
+fn other_output<'a>(
+    wm_state: &'a WindowManagerState,
+    settings: &Settings,
+    destination: &Workspace,
+) -> error::Result<Option<&'a Output>> {
+    if !matches!(settings.to, To::Output) || settings.index.is_some() {
+        return Err(SwayspaceError::Unsupported(format!(
+            "{:?} only applies to outputs",
+            settings.command
+        )));
+    }
+    Ok(wm_state
+        .output_showing(destination)
+        .filter(|&output| Some(output) != wm_state.focused_output()))
+}
 
-        let mut outputs = wm
-            .get_outputs()
-            .unwrap()
-            .iter()
-            .map(|o| Output {
-                x_pos: o.rect.x,
-                y_pos: o.rect.y,
-                name: o.name.clone(),
-            })
-            .collect::<Vec<_>>();
+// Sends the focused workspace over to the output showing `destination` and
+// keeps it focused there. Returns the commands and the workspace as it ends up.
+fn move_workspace(
+    wm_state: &WindowManagerState,
+    settings: &Settings,
+    destination: &Workspace,
+    config: &Config,
+) -> error::Result<(CommandBatch, Workspace)> {
+    let current = wm_state.current_workspace();
+    let output = match other_output(wm_state, settings, destination)? {
+        Some(output) => output,
+        None => return Ok((CommandBatch::default(), current.clone())),
+    };
+    let moved = wm_state.moved_to(&output.name, &config.range(output), settings.prefix_output);
+    let mut batch = CommandBatch::default();
+    if moved != *current {
+        batch.push(format!(
+            "rename workspace {} to {}",
+            quote(&current.name),
+            quote(&moved.name)
+        ));
+    }
+    let batch = batch
+        .then(format!("move workspace to output {}", quote(&output.name)))
+        .then(format!("workspace {}", moved.as_arg()));
+    Ok((batch, moved))
+}
 
-        outputs.sort();
+// Exchanges the visible workspaces of the focused output and the one showing
+// `destination`, which ends up focused on the focused output. The current
+// workspace goes first so that the other output is never left empty.
+fn swap_workspaces(
+    wm_state: &WindowManagerState,
+    settings: &Settings,
+    destination: &Workspace,
+) -> error::Result<(CommandBatch, Workspace)> {
+    let current = wm_state.current_workspace();
+    let (here, there) = match (
+        wm_state.focused_output(),
+        other_output(wm_state, settings, destination)?,
+    ) {
+        (Some(here), Some(there)) => (here, there),
+        _ => return Ok((CommandBatch::default(), current.clone())),
+    };
+    let batch = CommandBatch::default()
+        .then(format!("move workspace to output {}", quote(&there.name)))
+        .then(format!("workspace {}", destination.as_arg()))
+        .then(format!("move workspace to output {}", quote(&here.name)))
+        .then(format!("workspace {}", current.as_arg()))
+        .then(format!("workspace {}", destination.as_arg()));
+    Ok((batch, destination.clone()))
+}
 
-        let mut all_workspaces = wm.get_workspaces().unwrap();
-        let visible_workspaces = all_workspaces
-            .iter()
-            .filter(|w| w.visible)
-            .collect::<Vec<_>>();
-        let visible_workspace_per_output = outputs
-            .iter()
-            .filter_map(|o| {
-                visible_workspaces
-                    .iter()
-                    .find(|w| w.output == o.name)
-                    .map(|w| w.num)
-            })
-            .collect();
+// Focuses the window before or after the focused one, wherever it is
+fn focus_window(
+    wm_state: &WindowManagerState,
+    settings: &Settings,
+) -> error::Result<(CommandBatch, Workspace)> {
+    let dir = match (settings.command, settings.dir, settings.index) {
+        (Do::MoveFocusTo, dir @ (Direction::Prev | Direction::Next), None) => dir,
+        (command, dir, _) => {
+            return Err(SwayspaceError::Unsupported(format!(
+                "{:?} {:?} does not apply to windows",
+                command, dir
+            )))
+        }
+    };
+    let current = wm_state.current_workspace();
+    Ok(match wm_state.cycle_through_windows(settings, dir) {
+        Some(window) => (
+            CommandBatch::default().then(format!("[con_id={}] focus", window.id)),
+            wm_state
+                .find_workspace(&window.workspace)
+                .unwrap_or(current)
+                .clone(),
+        ),
+        None => (CommandBatch::default(), current.clone()),
+    })
+}
 
-        let current_workspace = all_workspaces.iter().find(|w| w.focused).unwrap().num;
-        let partition_point = all_workspaces
-            .iter_mut()
-            .partition_in_place(|w| w.output == focused_output_name);
-        let mut workspaces_on_focused_output = all_workspaces[0..partition_point]
-            .iter()
-            .map(|w| w.num)
-            .collect::<Vec<_>>();
-        workspaces_on_focused_output.sort_unstable();
-        let workspaces_on_unfocused_outputs = all_workspaces[partition_point..]
-            .iter()
-            .map(|w| w.num)
-            .collect::<Vec<_>>();
-        let max_workspace_on_focused_output = *workspaces_on_focused_output.iter().max().unwrap();
-        Self {
-            current_workspace,
-            workspaces_on_focused_output,
-            workspaces_on_unfocused_outputs,
-            max_workspace_on_focused_output,
-            visible_workspace_per_output,
+// Stashes the focused container, or shows the hidden scratchpad windows in
+// turn. The one shown before goes back first so that they do not pile up.
+fn scratchpad(
+    wm_state: &WindowManagerState,
+    settings: &Settings,
+) -> error::Result<(CommandBatch, Workspace)> {
+    let current = wm_state.current_workspace().clone();
+    let batch = CommandBatch::default();
+    match (settings.command, settings.dir, settings.index) {
+        (Do::MoveContainerTo | Do::SendContainerTo, _, None) => Ok((
+            batch.then("move container to scratchpad".to_string()),
+            current,
+        )),
+        (Do::MoveFocusTo, dir @ (Direction::Prev | Direction::Next), None) => {
+            let window = match wm_state.cycle_through_scratchpad(settings, dir) {
+                Some(window) => window,
+                None => return Ok((batch, current)),
+            };
+            let batch = match wm_state.shown_window() {
+                Some(shown) if shown.workspace != SCRATCHPAD => batch.then(format!(
+                    "[con_id={}] move container to scratchpad",
+                    shown.id
+                )),
+                _ => batch,
+            };
+            Ok((
+                batch
+                    .then(format!("[con_id={}] scratchpad show", window.id))
+                    .then(format!("[con_id={}] mark --add {}", window.id, SHOWN)),
+                current,
+            ))
         }
+        (command, dir, _) => Err(SwayspaceError::Unsupported(format!(
+            "{:?} {:?} does not apply to the scratchpad",
+            command, dir
+        ))),
     }
-    fn next_workspace(&self, workspaces: impl Iterator<Item = i32>) -> i32 {
-        workspaces
-            .skip_while(|&w| w != self.current_workspace)
-            .skip(1)
-            .next()
-            .unwrap_or(self.current_workspace)
-    }
-    fn cycle_through_workspaces_on_focused_output(&self, dynamic: bool, dir: Direction) -> i32 {
-        match (dir, dynamic) {
-            (Direction::Next, true) => self.next_workspace(
-                (1..).filter(|w| !self.workspaces_on_unfocused_outputs.contains(&w)),
-            ),
-            (Direction::Prev, true) => self.next_workspace(
-                (1..=self.max_workspace_on_focused_output)
-                    .filter(|w| !self.workspaces_on_unfocused_outputs.contains(&w))
-                    .rev()
-                    .cycle(),
-            ),
-            (Direction::Next, false) => {
-                self.next_workspace(self.workspaces_on_focused_output.iter().copied().cycle())
-            }
-            (Direction::Prev, false) => self.next_workspace(
-                self.workspaces_on_focused_output
-                    .iter()
-                    .copied()
-                    .rev()
-                    .cycle(),
-            ),
-        }
+}
+
+// Shows the workspaces of another group on every output at once. The
+// focused output comes last so that it keeps focus.
+fn switch_group(
+    wm_state: &WindowManagerState,
+    settings: &Settings,
+    config: &Config,
+) -> error::Result<(CommandBatch, Workspace)> {
+    if config.groups.is_empty() {
+        return Err(SwayspaceError::Unsupported(
+            "no groups are configured".to_string(),
+        ));
     }
-    fn cycle_through_outputs(&self, dir: Direction) -> i32 {
-        match dir {
-            Direction::Next => {
-                self.next_workspace(self.visible_workspace_per_output.iter().copied().cycle())
-            }
-            Direction::Prev => self.next_workspace(
-                self.visible_workspace_per_output
-                    .iter()
-                    .copied()
-                    .rev()
-                    .cycle(),
-            ),
+    let dir = match (settings.dir, settings.index) {
+        (dir @ (Direction::Prev | Direction::Next), None) => dir,
+        (dir, _) => {
+            return Err(SwayspaceError::Unsupported(format!(
+                "{:?} does not apply to groups",
+                dir
+            )))
+        }
+    };
+    let current = wm_state.current_workspace().clone();
+    let group = match wm_state.cycle_through_groups(&config.groups, dir, settings.at_end) {
+        Some(group) => group,
+        None => return Ok((CommandBatch::default(), current)),
+    };
+    let mut outputs = wm_state.outputs();
+    outputs.sort_by_key(|o| o.focused);
+    let here = outputs
+        .last()
+        .filter(|o| o.focused)
+        .ok_or(SwayspaceError::NoFocusedOutput)?;
+    let destination = group_workspace(group, &here.output.name);
+    let batch = match settings.command {
+        Do::MoveFocusTo => CommandBatch::default(),
+        Do::MoveContainerTo => CommandBatch::default().then(format!(
+            "move container to workspace {}",
+            destination.as_arg()
+        )),
+        Do::SendContainerTo => return Ok((commands(&destination, settings), current)),
+        command => {
+            return Err(SwayspaceError::Unsupported(format!(
+                "{:?} does not apply to groups",
+                command
+            )))
         }
+    };
+    let batch = outputs.iter().fold(batch, |batch, o| {
+        batch
+            .then(format!("focus output {}", quote(&o.output.name)))
+            .then(format!(
+                "workspace {}",
+                group_workspace(group, &o.output.name).as_arg()
+            ))
+    });
+    Ok((batch, destination))
+}
+
+// What a command amounts to: the batch to send to sway and the workspace
+// that ends up focused
+fn plan(
+    wm_state: &WindowManagerState,
+    settings: &Settings,
+    config: &Config,
+    history: &mut History,
+) -> error::Result<(CommandBatch, Workspace)> {
+    // Catches up with focus changes that did not go through swayspace
+    history.record(&wm_state.current_workspace().name);
+    // Only workspaces on the focused output can be created: past this point,
+    // nothing else has to handle `AtEnd::Create`
+    let uncreatable = match settings.to {
+        To::Output => Some("outputs cannot be created"),
+        To::AllWorkspaces => Some("workspaces are only created on the focused output"),
+        To::Window => Some("windows cannot be created"),
+        To::Scratchpad => Some("scratchpad windows cannot be created"),
+        To::Group => Some("groups are only created in the config file"),
+        To::Workspace | To::History => None,
+    };
+    if let Some(reason) = uncreatable.filter(|_| settings.at_end == AtEnd::Create) {
+        return Err(SwayspaceError::Unsupported(reason.to_string()));
+    }
+    match settings.to {
+        To::Window => return focus_window(wm_state, settings),
+        To::Scratchpad => return scratchpad(wm_state, settings),
+        To::Group => return switch_group(wm_state, settings, config),
+        _ => (),
     }
+    // Going back or forward through history only moves its cursor if focus
+    // goes along
+    let destination = match settings.command {
+        Do::SendContainerTo => pick_destination(wm_state, settings, &mut history.clone())?,
+        _ => pick_destination(wm_state, settings, history)?,
+    };
+    Ok(match settings.command {
+        Do::MoveWorkspaceTo => move_workspace(wm_state, settings, &destination, config)?,
+        Do::Swap => swap_workspaces(wm_state, settings, &destination)?,
+        // Focus stays behind
+        Do::SendContainerTo => (
+            commands(&destination, settings),
+            wm_state.current_workspace().clone(),
+        ),
+        _ => (commands(&destination, settings), destination),
+    })
 }
 
-fn pick_destination(wm_state: &WindowManagerState, opt: &Opt) -> i32 {
-    match (opt.to, opt.dir) {
-        (To::Workspace, dir) => {
-            wm_state.cycle_through_workspaces_on_focused_output(opt.dynamic, dir)
-        }
-        (To::Output, dir) => wm_state.cycle_through_outputs(dir),
+fn execute(
+    wm: &mut impl WindowManager,
+    wm_state: &WindowManagerState,
+    settings: &Settings,
+    config: &Config,
+    history: &mut History,
+) -> error::Result<()> {
+    let (batch, focused) = plan(wm_state, settings, config, history)?;
+    wm.run(&batch)?;
+    history.record(&focused.name);
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct DryRun<'a> {
+    state: &'a WindowManagerState,
+    commands: &'a [String],
+    focused: &'a Workspace,
+}
+
+// Outputs in order of their position, with their workspaces. Focused ones
+// are marked with a `*`, visible ones are in brackets.
+fn state(wm_state: &WindowManagerState, json: bool) -> String {
+    let outputs = wm_state.outputs();
+    if json {
+        let state = json!({
+            "focused_workspace": wm_state.current_workspace(),
+            "outputs": outputs,
+        });
+        return state.to_string();
     }
+    outputs
+        .iter()
+        .map(|o| {
+            let workspaces = o
+                .workspaces
+                .iter()
+                .map(|&w| {
+                    let label = w.label(&o.output.name);
+                    if w == o.visible_workspace {
+                        format!("[{}]", label)
+                    } else {
+                        label.to_string()
+                    }
+                })
+                .collect::<Vec<_>>();
+            format!(
+                "{}{} {}x{}+{}+{}: {}",
+                o.output.name,
+                if o.focused { "*" } else { "" },
+                o.output.width,
+                o.output.height,
+                o.output.x_pos,
+                o.output.y_pos,
+                workspaces.join(" ")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Explains what `execute` would do, leaving sway and the history alone
+fn dry_run(
+    wm_state: &WindowManagerState,
+    settings: &Settings,
+    config: &Config,
+    history: &History,
+    json: bool,
+) -> error::Result<String> {
+    let (batch, focused) = plan(wm_state, settings, config, &mut history.clone())?;
+    if json {
+        let report = DryRun {
+            state: wm_state,
+            commands: batch.steps(),
+            focused: &focused,
+        };
+        return Ok(serde_json::to_string(&report).expect("plain data serializes to JSON"));
+    }
+    let commands = if batch.is_empty() {
+        "would send nothing".to_string()
+    } else {
+        format!("would send: {}", batch)
+    };
+    Ok(format!(
+        "{:#?}\n{}\nwould focus: {}",
+        wm_state, commands, focused.name
+    ))
+}
+
+// Sway only destroys a workspace once it loses focus, so this needs a fresh
+// look at the state after navigating away from it. The focused output may
+// have changed along the way, and with it the range of numbers to use.
+fn renumber(wm: &mut impl WindowManager, opt: &Opt, config: &Config) -> error::Result<()> {
+    let wm_state = WindowManagerState::from_wm(wm)?;
+    let settings = Settings::resolve(opt, config, wm_state.focused_output());
+    wm.run(&wm_state.renumbering(&settings))
+}
+
+fn run(
+    wm: &mut impl WindowManager,
+    opt: &Opt,
+    config: &Config,
+    history: &mut History,
+) -> error::Result<()> {
+    let wm_state = WindowManagerState::from_wm(wm)?;
+    let settings = Settings::resolve(opt, config, wm_state.focused_output());
+    if let Do::State = settings.command {
+        println!("{}", state(&wm_state, opt.json));
+        return Ok(());
+    }
+    if opt.dry_run {
+        println!(
+            "{}",
+            dry_run(&wm_state, &settings, config, history, opt.json)?
+        );
+        return Ok(());
+    }
+    execute(wm, &wm_state, &settings, config, history)?;
+    if settings.renumber {
+        renumber(wm, opt, config)?;
+    }
+    Ok(())
 }
 
 fn main() {
     pretty_env_logger::init();
     let opt = Opt::from_args();
-    let mut wm = swayipc::Connection::new().unwrap();
-    let wm_state = WindowManagerState::from_wm(&mut wm);
-    match opt.command {
-        Do::MoveFocusTo => {
-            let destination = pick_destination(&wm_state, &opt);
-            wm.run_command(format!("workspace number {}", destination))
-                .unwrap();
+    // Reports are printed here rather than in the daemon
+    let forward = |command: Option<Do>| {
+        if opt.dry_run || matches!(command, Some(Do::State)) {
+            None
+        } else {
+            daemon::forward(&std::env::args().skip(1).collect::<Vec<_>>())
         }
-        Do::MoveContainerTo => {
-            let destination = pick_destination(&wm_state, &opt);
-            wm.run_command(format!(
-                "move container to workspace number {}",
-                destination
-            ))
-            .unwrap();
-            wm.run_command(format!("workspace number {}", destination))
-                .unwrap();
+    };
+    let result = Config::load().and_then(|config| match opt.command.or(config.command) {
+        Some(Do::Daemon) => daemon::run(&opt, &config),
+        Some(Do::Bar) => bar::run(&opt, &config),
+        command => forward(command).unwrap_or_else(|| {
+            let mut history = History::load();
+            let result =
+                Sway::connect().and_then(|mut wm| run(&mut wm, &opt, &config, &mut history));
+            history.save();
+            result
+        }),
+    });
+    if let Err(e) = result {
+        eprintln!("swayspace: {}", e);
+        std::process::exit(e.exit_code());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use backend::{FakeWindowManager, SCRATCHPAD};
+
+    fn opt(args: &str) -> Opt {
+        Opt::from_iter(std::iter::once("swayspace").chain(args.split_whitespace()))
+    }
+
+    // Runs `args` against `wm` and returns the commands sent to it
+    fn run_on(mut wm: FakeWindowManager, args: &str) -> Vec<String> {
+        run(
+            &mut wm,
+            &opt(args),
+            &Config::default(),
+            &mut History::default(),
+        )
+        .unwrap();
+        wm.commands
+    }
+
+    // eDP-1 (left): 7 | DP-1 (middle, focused): 1, 4 | HDMI-A-1 (right): 3, 5
+    fn three_outputs() -> FakeWindowManager {
+        FakeWindowManager::default()
+            .with_output("DP-1", 0, 0)
+            .with_output("HDMI-A-1", 1920, 0)
+            .with_output("eDP-1", -1920, 0)
+            .with_workspace("1", "DP-1")
+            .with_workspace("4", "DP-1")
+            .with_workspace("3", "HDMI-A-1")
+            .with_workspace("5", "HDMI-A-1")
+            .with_workspace("7", "eDP-1")
+            .showing("5")
+            .showing("7")
+            .focusing("4")
+    }
+
+    fn focus(workspace: &str) -> Vec<String> {
+        vec![format!("workspace {}", workspace)]
+    }
+
+    fn carry(workspace: &str) -> Vec<String> {
+        vec![format!(
+            "move container to workspace {0}; workspace {0}",
+            workspace
+        )]
+    }
+
+    // Steps as they go to sway in a single message
+    fn batch(steps: &[&str]) -> String {
+        steps.join("; ")
+    }
+
+    #[test]
+    fn move_focus_to_next_workspace_wraps_around() {
+        let commands = run_on(three_outputs(), "move-focus-to workspace next");
+        assert_eq!(commands, focus("number 1"));
+    }
+
+    #[test]
+    fn move_focus_to_next_workspace_dynamic_creates_one_unused_by_other_outputs() {
+        let commands = run_on(three_outputs(), "move-focus-to workspace next --dynamic");
+        assert_eq!(commands, focus("number 6"));
+    }
+
+    #[test]
+    fn move_focus_to_prev_workspace() {
+        let commands = run_on(three_outputs(), "move-focus-to workspace prev");
+        assert_eq!(commands, focus("number 1"));
+    }
+
+    #[test]
+    fn move_focus_to_prev_workspace_dynamic_fills_gaps() {
+        let commands = run_on(three_outputs(), "move-focus-to workspace prev --dynamic");
+        assert_eq!(commands, focus("number 2"));
+    }
+
+    #[test]
+    fn move_focus_to_next_output() {
+        let commands = run_on(three_outputs(), "move-focus-to output next");
+        assert_eq!(commands, focus("number 5"));
+    }
+
+    #[test]
+    fn move_focus_to_next_output_dynamic() {
+        let commands = run_on(three_outputs(), "move-focus-to output next --dynamic");
+        assert_eq!(commands, focus("number 5"));
+    }
+
+    #[test]
+    fn move_focus_to_prev_output() {
+        let commands = run_on(three_outputs(), "move-focus-to output prev");
+        assert_eq!(commands, focus("number 7"));
+    }
+
+    #[test]
+    fn move_focus_to_prev_output_dynamic() {
+        let commands = run_on(three_outputs(), "move-focus-to output prev --dynamic");
+        assert_eq!(commands, focus("number 7"));
+    }
+
+    #[test]
+    fn move_container_to_next_workspace_wraps_around() {
+        let commands = run_on(three_outputs(), "move-container-to workspace next");
+        assert_eq!(commands, carry("number 1"));
+    }
+
+    #[test]
+    fn move_container_to_next_workspace_dynamic_creates_one_unused_by_other_outputs() {
+        let commands = run_on(
+            three_outputs(),
+            "move-container-to workspace next --dynamic",
+        );
+        assert_eq!(commands, carry("number 6"));
+    }
+
+    #[test]
+    fn move_container_to_prev_workspace() {
+        let commands = run_on(three_outputs(), "move-container-to workspace prev");
+        assert_eq!(commands, carry("number 1"));
+    }
+
+    #[test]
+    fn move_container_to_prev_workspace_dynamic_fills_gaps() {
+        let commands = run_on(
+            three_outputs(),
+            "move-container-to workspace prev --dynamic",
+        );
+        assert_eq!(commands, carry("number 2"));
+    }
+
+    #[test]
+    fn move_container_to_next_output() {
+        let commands = run_on(three_outputs(), "move-container-to output next");
+        assert_eq!(commands, carry("number 5"));
+    }
+
+    #[test]
+    fn move_container_to_next_output_dynamic() {
+        let commands = run_on(three_outputs(), "move-container-to output next --dynamic");
+        assert_eq!(commands, carry("number 5"));
+    }
+
+    #[test]
+    fn move_container_to_prev_output() {
+        let commands = run_on(three_outputs(), "move-container-to output prev");
+        assert_eq!(commands, carry("number 7"));
+    }
+
+    #[test]
+    fn move_container_to_prev_output_dynamic() {
+        let commands = run_on(three_outputs(), "move-container-to output prev --dynamic");
+        assert_eq!(commands, carry("number 7"));
+    }
+
+    #[test]
+    fn next_workspace_on_output_with_a_single_workspace_stays_put() {
+        let commands = run_on(
+            three_outputs().focusing("7"),
+            "move-focus-to workspace next",
+        );
+        assert_eq!(commands, focus("number 7"));
+    }
+
+    #[test]
+    fn nothing_focused_is_an_error() {
+        let mut wm = FakeWindowManager::default()
+            .with_output("DP-1", 0, 0)
+            .with_workspace("1", "DP-1")
+            .showing("1");
+        let error = run(
+            &mut wm,
+            &opt(""),
+            &Config::default(),
+            &mut History::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(error, SwayspaceError::NoFocusedOutput));
+        assert_eq!(error.exit_code(), 4);
+    }
+
+    #[test]
+    fn rejected_step_is_reported() {
+        let mut wm = three_outputs();
+        wm.rejecting = Some("workspace".to_string());
+        let opt = opt("move-container-to workspace next");
+        let error = run(&mut wm, &opt, &Config::default(), &mut History::default()).unwrap_err();
+        match error {
+            SwayspaceError::CommandRejected { command, .. } => {
+                assert_eq!(command, "workspace number 1")
+            }
+            error => panic!("unexpected {:?}", error),
         }
     }
+
+    // DP-1 (focused): 1, 3:web, mail | HDMI-A-1: 2
+    fn named_workspaces(focused: &str) -> FakeWindowManager {
+        FakeWindowManager::default()
+            .with_output("DP-1", 0, 0)
+            .with_output("HDMI-A-1", 1920, 0)
+            .with_workspace("1", "DP-1")
+            .with_workspace("3:web", "DP-1")
+            .with_workspace("mail", "DP-1")
+            .with_workspace("2", "HDMI-A-1")
+            .showing("2")
+            .focusing(focused)
+    }
+
+    #[test]
+    fn named_workspaces_come_after_numbered_ones() {
+        let commands = run_on(named_workspaces("3:web"), "move-focus-to workspace next");
+        assert_eq!(commands, focus("\"mail\""));
+        let commands = run_on(named_workspaces("mail"), "move-focus-to workspace next");
+        assert_eq!(commands, focus("number 1"));
+    }
+
+    #[test]
+    fn labelled_workspaces_keep_their_label() {
+        let commands = run_on(named_workspaces("1"), "move-container-to workspace next");
+        assert_eq!(commands, carry("\"3:web\""));
+    }
+
+    #[test]
+    fn dynamic_next_creates_a_workspace_after_the_named_ones() {
+        let commands = run_on(
+            named_workspaces("3:web"),
+            "move-focus-to workspace next --dynamic",
+        );
+        assert_eq!(commands, focus("\"mail\""));
+        let commands = run_on(
+            named_workspaces("mail"),
+            "move-focus-to workspace next --dynamic",
+        );
+        assert_eq!(commands, focus("number 4"));
+    }
+
+    #[test]
+    fn dynamic_prev_wraps_around_to_named_workspaces() {
+        let commands = run_on(
+            named_workspaces("1"),
+            "move-focus-to workspace prev --dynamic",
+        );
+        assert_eq!(commands, focus("\"mail\""));
+    }
+
+    #[test]
+    fn workspace_names_are_quoted() {
+        let workspace = Workspace {
+            num: None,
+            name: "say \"hi\"".to_string(),
+            windows: 0,
+        };
+        assert_eq!(workspace.as_arg(), "\"say \\\"hi\\\"\"");
+    }
+
+    #[test]
+    fn history_toggles_between_the_two_most_recent_workspaces() {
+        let opt = opt("move-focus-to history toggle");
+        let mut history = History::default();
+        history.record("7");
+        let mut wm = three_outputs();
+        run(&mut wm, &opt, &Config::default(), &mut history).unwrap();
+        assert_eq!(wm.commands, focus("number 7"));
+        let mut wm = three_outputs().focusing("7");
+        run(&mut wm, &opt, &Config::default(), &mut history).unwrap();
+        assert_eq!(wm.commands, focus("number 4"));
+    }
+
+    #[test]
+    fn sending_a_window_back_in_history_keeps_the_history() {
+        let mut history = History::default();
+        for workspace in ["1", "7", "4", "3"] {
+            history.record(workspace);
+        }
+        // Back on 4, with 3 ahead
+        history.back(|_| true);
+        let before = history.clone();
+        let mut wm = three_outputs();
+        let opt = opt("send-container-to history prev");
+        run(&mut wm, &opt, &Config::default(), &mut history).unwrap();
+        assert_eq!(wm.commands, ["move container to workspace number 7"]);
+        assert_eq!(history, before);
+    }
+
+    #[test]
+    fn toggle_only_applies_to_history() {
+        let mut wm = three_outputs();
+        let opt = opt("move-focus-to output toggle");
+        let error = run(&mut wm, &opt, &Config::default(), &mut History::default()).unwrap_err();
+        assert!(matches!(error, SwayspaceError::Unsupported(_)));
+    }
+
+    #[test]
+    fn index_counts_the_workspaces_of_the_focused_output() {
+        let commands = run_on(three_outputs(), "move-focus-to --index 2");
+        assert_eq!(commands, focus("number 4"));
+        let commands = run_on(
+            three_outputs().focusing("5"),
+            "move-container-to --index first",
+        );
+        assert_eq!(commands, carry("number 3"));
+        let commands = run_on(three_outputs(), "move-focus-to --index last");
+        assert_eq!(commands, focus("number 4"));
+    }
+
+    #[test]
+    fn index_past_the_end_stays_put_unless_dynamic() {
+        let commands = run_on(three_outputs(), "move-focus-to --index 5");
+        assert_eq!(commands, focus("number 4"));
+        let commands = run_on(three_outputs(), "move-focus-to --index 2 --dynamic");
+        assert_eq!(commands, focus("number 2"));
+        let commands = run_on(three_outputs(), "move-focus-to --index 5 --dynamic");
+        assert_eq!(commands, focus("number 8"));
+    }
+
+    #[test]
+    fn index_is_relative_to_the_range_of_the_output() {
+        let wm = three_outputs().focusing("5");
+        let commands = with_ranges(wm, "move-focus-to --index 4 --dynamic");
+        assert_eq!(commands, focus("number 12"));
+    }
+
+    #[test]
+    fn index_only_applies_to_workspaces() {
+        let mut wm = three_outputs();
+        let opt = opt("move-focus-to output next --index 2");
+        let error = run(&mut wm, &opt, &Config::default(), &mut History::default()).unwrap_err();
+        assert!(matches!(error, SwayspaceError::Unsupported(_)));
+        assert!(wm.commands.is_empty());
+    }
+
+    #[test]
+    fn index_must_be_a_position() {
+        assert!(Opt::from_iter_safe(["swayspace", "--index", "0"]).is_err());
+        assert!(Opt::from_iter_safe(["swayspace", "--index", "middle"]).is_err());
+    }
+
+    #[test]
+    fn send_container_leaves_focus_behind() {
+        let commands = run_on(three_outputs(), "send-container-to workspace next");
+        assert_eq!(commands, ["move container to workspace number 1"]);
+        let commands = run_on(three_outputs(), "send-container-to output right");
+        assert_eq!(commands, ["move container to workspace number 5"]);
+    }
+
+    #[test]
+    fn move_workspace_to_next_output() {
+        let commands = run_on(three_outputs(), "move-workspace-to output next");
+        assert_eq!(
+            commands,
+            [batch(&[
+                "move workspace to output \"HDMI-A-1\"",
+                "workspace number 4",
+            ])]
+        );
+    }
+
+    #[test]
+    fn move_workspace_resolves_collisions_on_the_destination_output() {
+        let wm = three_outputs()
+            .with_workspace("4:mail", "HDMI-A-1")
+            .showing("4:mail");
+        let commands = run_on(wm, "move-workspace-to output right");
+        assert_eq!(
+            commands,
+            [batch(&[
+                "rename workspace \"4\" to \"6\"",
+                "move workspace to output \"HDMI-A-1\"",
+                "workspace number 6",
+            ])]
+        );
+    }
+
+    #[test]
+    fn move_workspace_takes_a_number_in_the_range_of_the_destination() {
+        let wm = three_outputs()
+            .with_workspace("4:web", "DP-1")
+            .focusing("4:web");
+        let commands = with_ranges(wm, "move-workspace-to output right");
+        assert_eq!(
+            commands,
+            [batch(&[
+                "rename workspace \"4:web\" to \"11:web\"",
+                "move workspace to output \"HDMI-A-1\"",
+                "workspace \"11:web\"",
+            ])]
+        );
+    }
+
+    #[test]
+    fn move_workspace_stays_put_without_an_output_that_way() {
+        let commands = run_on(three_outputs(), "move-workspace-to output up");
+        assert!(commands.is_empty());
+    }
+
+    #[test]
+    fn move_workspace_only_applies_to_outputs() {
+        let mut wm = three_outputs();
+        let opt = opt("move-workspace-to workspace next");
+        let error = run(&mut wm, &opt, &Config::default(), &mut History::default()).unwrap_err();
+        assert!(matches!(error, SwayspaceError::Unsupported(_)));
+    }
+
+    #[test]
+    fn swap_exchanges_visible_workspaces_in_one_go() {
+        let commands = run_on(three_outputs(), "swap output left");
+        assert_eq!(
+            commands,
+            [batch(&[
+                "move workspace to output \"eDP-1\"",
+                "workspace number 7",
+                "move workspace to output \"DP-1\"",
+                "workspace number 4",
+                "workspace number 7",
+            ])]
+        );
+    }
+
+    #[test]
+    fn swap_without_another_output_does_nothing() {
+        let commands = run_on(three_outputs(), "swap output down");
+        assert!(commands.is_empty());
+        let mut wm = three_outputs();
+        let error = run(
+            &mut wm,
+            &opt("swap history toggle"),
+            &Config::default(),
+            &mut History::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(error, SwayspaceError::Unsupported(_)));
+    }
+
+    #[test]
+    fn state_lists_outputs_in_order_of_position() {
+        let mut wm = three_outputs();
+        let wm_state = WindowManagerState::from_wm(&mut wm).unwrap();
+        assert_eq!(
+            state(&wm_state, false),
+            [
+                "eDP-1 1920x1080+-1920+0: [7]",
+                "DP-1* 1920x1080+0+0: 1 [4]",
+                "HDMI-A-1 1920x1080+1920+0: 3 [5]",
+            ]
+            .join("\n")
+        );
+        let state: serde_json::Value = serde_json::from_str(&state(&wm_state, true)).unwrap();
+        assert_eq!(state["focused_workspace"]["name"], "4");
+        assert_eq!(state["outputs"][2]["name"], "HDMI-A-1");
+        assert_eq!(state["outputs"][2]["x_pos"], 1920);
+        assert_eq!(state["outputs"][2]["visible_workspace"]["name"], "5");
+        assert_eq!(state["outputs"][2]["workspaces"][0]["num"], 3);
+    }
+
+    #[test]
+    fn dry_run_sends_nothing() {
+        let mut wm = three_outputs();
+        let mut history = History::default();
+        let opt = opt("move-container-to output next --dry-run");
+        run(&mut wm, &opt, &Config::default(), &mut history).unwrap();
+        assert!(wm.commands.is_empty());
+        assert_eq!(history, History::default());
+    }
+
+    #[test]
+    fn dry_run_reports_commands_and_state_as_json() {
+        let mut wm = three_outputs();
+        let wm_state = WindowManagerState::from_wm(&mut wm).unwrap();
+        let settings = Settings::resolve(
+            &opt("move-container-to output next"),
+            &Config::default(),
+            wm_state.focused_output(),
+        );
+        let report = dry_run(
+            &wm_state,
+            &settings,
+            &Config::default(),
+            &History::default(),
+            true,
+        )
+        .unwrap();
+        let report: serde_json::Value = serde_json::from_str(&report).unwrap();
+        assert_eq!(
+            report["commands"],
+            serde_json::json!(["move container to workspace number 5", "workspace number 5"])
+        );
+        assert_eq!(report["focused"]["name"], "5");
+        assert_eq!(report["state"]["focused_output"], "DP-1");
+    }
+
+    #[test]
+    fn stopping_at_the_end_stays_on_the_last_workspace_or_output() {
+        let commands = run_on(
+            three_outputs(),
+            "move-focus-to workspace next --at-end stop",
+        );
+        assert_eq!(commands, focus("number 4"));
+        let wm = three_outputs().focusing("5");
+        let commands = run_on(wm, "move-focus-to output next --at-end stop");
+        assert_eq!(commands, focus("number 5"));
+    }
+
+    #[test]
+    fn creating_at_the_end_does_not_need_dynamic() {
+        let commands = run_on(
+            three_outputs(),
+            "move-focus-to workspace next --at-end create",
+        );
+        assert_eq!(commands, focus("number 6"));
+        let commands = run_on(
+            three_outputs(),
+            "move-focus-to workspace prev --at-end create",
+        );
+        assert_eq!(commands, focus("number 1"));
+    }
+
+    #[test]
+    fn dynamic_workspaces_can_wrap_instead_of_creating() {
+        let commands = run_on(
+            three_outputs(),
+            "move-focus-to workspace next --dynamic --at-end wrap",
+        );
+        assert_eq!(commands, focus("number 1"));
+    }
+
+    #[test]
+    fn outputs_cannot_be_created() {
+        let mut wm = three_outputs();
+        let opt = opt("move-focus-to output next --at-end create");
+        let error = run(&mut wm, &opt, &Config::default(), &mut History::default()).unwrap_err();
+        assert!(matches!(error, SwayspaceError::Unsupported(_)));
+    }
+
+    #[test]
+    fn skip_empty_goes_past_workspaces_without_windows() {
+        let wm = three_outputs()
+            .with_workspace("2", "DP-1")
+            .with_windows("1", 1)
+            .focusing("1");
+        let commands = run_on(wm, "move-focus-to workspace next --skip-empty");
+        assert_eq!(commands, focus("number 1"));
+        let wm = three_outputs()
+            .with_workspace("2", "DP-1")
+            .with_windows("2", 3)
+            .focusing("1");
+        let commands = run_on(wm, "move-focus-to workspace prev --skip-empty");
+        assert_eq!(commands, focus("number 2"));
+    }
+
+    #[test]
+    fn skip_empty_still_creates_dynamic_workspaces() {
+        let commands = run_on(
+            three_outputs(),
+            "move-focus-to workspace next --dynamic --skip-empty",
+        );
+        assert_eq!(commands, focus("number 6"));
+        let wm = three_outputs().with_windows("1", 2);
+        let commands = run_on(wm, "move-focus-to workspace prev --dynamic --skip-empty");
+        assert_eq!(commands, focus("number 1"));
+    }
+
+    #[test]
+    fn all_workspaces_crosses_outputs_from_left_to_right() {
+        let commands = run_on(three_outputs(), "move-focus-to allworkspaces next");
+        assert_eq!(commands, focus("number 3"));
+        let commands = run_on(three_outputs(), "move-container-to AllWorkspaces prev");
+        assert_eq!(commands, carry("number 1"));
+        let wm = three_outputs().focusing("7");
+        let commands = run_on(wm, "move-focus-to allworkspaces prev");
+        assert_eq!(commands, focus("number 5"));
+        let wm = three_outputs().focusing("7");
+        let commands = run_on(wm, "move-focus-to allworkspaces prev --at-end stop");
+        assert_eq!(commands, focus("number 7"));
+    }
+
+    #[test]
+    fn all_workspaces_ignores_dynamic_creation() {
+        let wm = three_outputs().focusing("5");
+        let commands = run_on(wm, "move-focus-to allworkspaces next --dynamic");
+        assert_eq!(commands, focus("number 7"));
+    }
+
+    // 1: term on 7 (eDP-1) | 2: term, 3: firefox on 4 (DP-1, focused)
+    // | 4: firefox on 5 (HDMI-A-1)
+    fn with_windows() -> FakeWindowManager {
+        three_outputs()
+            .with_window("7", "term")
+            .with_window("4", "term")
+            .with_window("4", "firefox")
+            .with_window("5", "firefox")
+    }
+
+    #[test]
+    fn windows_are_cycled_across_workspaces() {
+        let wm = with_windows().focusing_window(3);
+        let commands = run_on(wm, "move-focus-to window next");
+        assert_eq!(commands, ["[con_id=4] focus"]);
+        let wm = with_windows().focusing_window(1);
+        let commands = run_on(wm, "move-focus-to window prev");
+        assert_eq!(commands, ["[con_id=4] focus"]);
+        let wm = with_windows().focusing_window(1);
+        let commands = run_on(wm, "move-focus-to window prev --at-end stop");
+        assert!(commands.is_empty());
+    }
+
+    #[test]
+    fn windows_can_be_filtered_by_app_id() {
+        let wm = with_windows().focusing_window(2);
+        let commands = run_on(wm, "move-focus-to window next --app-id firefox");
+        assert_eq!(commands, ["[con_id=3] focus"]);
+        let wm = with_windows().focusing_window(2);
+        let commands = run_on(wm, "move-focus-to window prev --app-id firefox");
+        assert_eq!(commands, ["[con_id=4] focus"]);
+    }
+
+    #[test]
+    fn windows_can_only_be_focused() {
+        let opt = opt("move-container-to window next");
+        let error = run(
+            &mut with_windows().focusing_window(2),
+            &opt,
+            &Config::default(),
+            &mut History::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(error, SwayspaceError::Unsupported(_)));
+    }
+
+    // 5: term and 6: firefox are hidden, 7 is a tiled window on 4
+    fn with_scratchpad() -> FakeWindowManager {
+        with_windows()
+            .with_window(SCRATCHPAD, "term")
+            .with_window(SCRATCHPAD, "firefox")
+            .with_window("4", "term")
+            .focusing_window(7)
+    }
+
+    #[test]
+    fn containers_are_stashed_in_the_scratchpad() {
+        let commands = run_on(with_scratchpad(), "move-container-to scratchpad");
+        assert_eq!(commands, ["move container to scratchpad"]);
+    }
+
+    #[test]
+    fn scratchpad_windows_are_shown_in_turn() {
+        let commands = run_on(with_scratchpad(), "move-focus-to scratchpad next");
+        assert_eq!(commands, [show(5)]);
+        let commands = run_on(with_scratchpad(), "move-focus-to scratchpad prev");
+        assert_eq!(commands, [show(6)]);
+        let commands = run_on(
+            with_scratchpad(),
+            "move-focus-to scratchpad next --app-id firefox",
+        );
+        assert_eq!(commands, [show(6)]);
+        // Hidden again with sway's own toggle: the next one is after it
+        let commands = run_on(with_scratchpad().shown(5), "move-focus-to scratchpad next");
+        assert_eq!(commands, [show(6)]);
+    }
+
+    fn show(id: i64) -> String {
+        batch(&[
+            &format!("[con_id={}] scratchpad show", id),
+            &format!("[con_id={}] mark --add _swayspace_shown", id),
+        ])
+    }
+
+    #[test]
+    fn shown_scratchpad_windows_go_back_before_the_next_one() {
+        // 5 is shown on 4, leaving 6 hidden
+        let mut wm = with_scratchpad().shown(5);
+        wm.windows[4].workspace = "4".to_string();
+        let commands = run_on(wm.focusing_window(5), "move-focus-to scratchpad next");
+        assert_eq!(
+            commands,
+            [format!(
+                "[con_id=5] move container to scratchpad; {}",
+                show(6)
+            )]
+        );
+    }
+
+    fn with_groups(mut wm: FakeWindowManager, args: &str) -> error::Result<Vec<String>> {
+        let config = Config {
+            groups: vec!["work".to_string(), "personal".to_string()],
+            ..Default::default()
+        };
+        run(&mut wm, &opt(args), &config, &mut History::default())?;
+        Ok(wm.commands)
+    }
+
+    fn switch_to(group: &str) -> Vec<String> {
+        let steps = ["eDP-1", "HDMI-A-1", "DP-1"]
+            .iter()
+            .flat_map(|output| {
+                [
+                    format!("focus output \"{}\"", output),
+                    format!("workspace \"{}@{}\"", group, output),
+                ]
+            })
+            .collect::<Vec<_>>();
+        vec![steps.join("; ")]
+    }
+
+    #[test]
+    fn groups_switch_every_output_at_once() {
+        let commands = with_groups(three_outputs(), "move-focus-to group next").unwrap();
+        assert_eq!(commands, switch_to("work"));
+        let wm = three_outputs()
+            .with_workspace("work@DP-1", "DP-1")
+            .focusing("work@DP-1");
+        let commands = with_groups(wm, "move-focus-to group next").unwrap();
+        assert_eq!(commands, switch_to("personal"));
+        let wm = three_outputs()
+            .with_workspace("work@DP-1", "DP-1")
+            .focusing("work@DP-1");
+        let commands = with_groups(wm, "move-focus-to group prev").unwrap();
+        assert_eq!(commands, switch_to("personal"));
+    }
+
+    #[test]
+    fn containers_can_be_taken_along_to_another_group() {
+        let commands = with_groups(three_outputs(), "move-container-to group next").unwrap();
+        assert_eq!(
+            commands,
+            [format!(
+                "move container to workspace \"work@DP-1\"; {}",
+                switch_to("work")[0]
+            )]
+        );
+        let commands = with_groups(three_outputs(), "send-container-to group next").unwrap();
+        assert_eq!(commands, ["move container to workspace \"work@DP-1\""]);
+    }
+
+    #[test]
+    fn groups_must_be_configured() {
+        let error = run(
+            &mut three_outputs(),
+            &opt("move-focus-to group next"),
+            &Config::default(),
+            &mut History::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(error, SwayspaceError::Unsupported(_)));
+    }
+
+    #[test]
+    fn prefixed_workspaces_count_on_their_own_output() {
+        let commands = run_on(
+            three_outputs().focusing("1"),
+            "move-focus-to workspace next --dynamic --prefix-output",
+        );
+        assert_eq!(commands, focus("\"2:DP-1\""));
+        let commands = run_on(
+            three_outputs(),
+            "move-focus-to workspace next --dynamic --prefix-output",
+        );
+        assert_eq!(commands, focus("\"5:DP-1\""));
+    }
+
+    #[test]
+    fn prefixed_workspaces_follow_their_output_when_moved() {
+        let commands = run_on(
+            three_outputs(),
+            "move-workspace-to output right --prefix-output",
+        );
+        assert_eq!(
+            commands,
+            [batch(&[
+                "rename workspace \"4\" to \"4:HDMI-A-1\"",
+                "move workspace to output \"HDMI-A-1\"",
+                "workspace \"4:HDMI-A-1\"",
+            ])]
+        );
+    }
+
+    #[test]
+    fn prefixes_are_left_out_when_printing() {
+        let mut wm = three_outputs()
+            .with_workspace("2:DP-1", "DP-1")
+            .focusing("2:DP-1");
+        let wm_state = WindowManagerState::from_wm(&mut wm).unwrap();
+        assert!(state(&wm_state, false).contains("DP-1* 1920x1080+0+0: 1 [2] 4"));
+    }
+
+    #[test]
+    fn workspaces_are_told_apart_by_name_only() {
+        let busy = Workspace {
+            windows: 3,
+            ..Workspace::numbered(4)
+        };
+        assert_eq!(busy, Workspace::numbered(4));
+        assert_ne!(busy, Workspace::numbered(5));
+    }
+
+    #[test]
+    fn renumbering_closes_gaps_after_navigating() {
+        let commands = run_on(three_outputs(), "move-focus-to workspace next --renumber");
+        assert_eq!(commands[1..], ["rename workspace \"4\" to \"2\""]);
+    }
+
+    // HDMI-A-1 owns workspaces 11 to 19
+    fn with_ranges(mut wm: FakeWindowManager, args: &str) -> Vec<String> {
+        let mut config = Config::default();
+        config.outputs.insert(
+            "HDMI-A-1".to_string(),
+            config::OutputConfig {
+                range: Some([11, 19]),
+                ..Default::default()
+            },
+        );
+        run(&mut wm, &opt(args), &config, &mut History::default()).unwrap();
+        wm.commands
+    }
+
+    #[test]
+    fn dynamic_workspaces_are_created_in_the_range_of_their_output() {
+        let wm = three_outputs().focusing("5");
+        let commands = with_ranges(wm, "move-focus-to workspace next --dynamic");
+        assert_eq!(commands, focus("number 11"));
+        let wm = three_outputs()
+            .with_workspace("13", "HDMI-A-1")
+            .focusing("13");
+        let commands = with_ranges(wm, "move-focus-to workspace prev --dynamic");
+        assert_eq!(commands, focus("number 12"));
+    }
+
+    #[test]
+    fn outputs_without_a_range_are_unaffected() {
+        let commands = with_ranges(three_outputs(), "move-focus-to workspace next --dynamic");
+        assert_eq!(commands, focus("number 6"));
+    }
+
+    #[test]
+    fn renumbering_starts_at_the_range_of_the_output() {
+        let wm = three_outputs().focusing("5");
+        let commands = with_ranges(wm, "move-focus-to history prev --renumber");
+        assert_eq!(
+            commands[1..],
+            [batch(&[
+                "rename workspace \"5\" to \"12\"",
+                "rename workspace \"3\" to \"11\"",
+            ])]
+        );
+    }
+
+    #[test]
+    fn renumbering_never_targets_a_name_still_in_use() {
+        let wm = three_outputs()
+            .with_workspace("11", "HDMI-A-1")
+            .with_workspace("13", "HDMI-A-1")
+            .focusing("5");
+        let commands = with_ranges(wm, "move-focus-to history prev --renumber");
+        assert_eq!(
+            commands[1..],
+            [batch(&[
+                "rename workspace \"13\" to \"14\"",
+                "rename workspace \"11\" to \"13\"",
+                "rename workspace \"5\" to \"12\"",
+                "rename workspace \"3\" to \"11\"",
+            ])]
+        );
+    }
+
+    #[test]
+    fn renumbering_stays_within_the_range() {
+        let mut wm = three_outputs()
+            .with_workspace("12", "HDMI-A-1")
+            .focusing("12");
+        let mut config = Config::default();
+        config.outputs.insert(
+            "HDMI-A-1".to_string(),
+            config::OutputConfig {
+                range: Some([11, 12]),
+                ..Default::default()
+            },
+        );
+        let opt = opt("move-focus-to history prev --renumber");
+        run(&mut wm, &opt, &config, &mut History::default()).unwrap();
+        // 3 and 5 do not fit: only 12 moves
+        assert_eq!(
+            wm.commands[1..],
+            [batch(&["rename workspace \"12\" to \"11\""])]
+        );
+    }
+
+    #[test]
+    fn renumbering_keeps_labels_and_skips_numbers_used_elsewhere() {
+        let wm = FakeWindowManager::default()
+            .with_output("DP-1", 0, 0)
+            .with_output("HDMI-A-1", 1920, 0)
+            .with_workspace("1", "DP-1")
+            .with_workspace("4:web", "DP-1")
+            .with_workspace("6", "DP-1")
+            .with_workspace("mail", "DP-1")
+            .with_workspace("2", "HDMI-A-1")
+            .showing("2")
+            .focusing("1");
+        let commands = run_on(wm, "move-focus-to history prev --renumber");
+        assert_eq!(
+            commands,
+            [
+                "workspace number 1".to_string(),
+                batch(&[
+                    "rename workspace \"4:web\" to \"3:web\"",
+                    "rename workspace \"6\" to \"4\"",
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn renumbering_prefixes_the_numbers_used_elsewhere() {
+        let wm = FakeWindowManager::default()
+            .with_output("DP-1", 0, 0)
+            .with_output("HDMI-A-1", 1920, 0)
+            .with_workspace("1", "DP-1")
+            .with_workspace("4", "DP-1")
+            .with_workspace("2", "HDMI-A-1")
+            .showing("2")
+            .focusing("1");
+        let commands = run_on(wm, "move-focus-to history prev --renumber --prefix-output");
+        assert_eq!(commands[1..], ["rename workspace \"4\" to \"2:DP-1\""]);
+    }
+
+    // DP-1 (focused) sits on top of DP-2, HDMI-A-1 is right of DP-1
+    fn stacked_outputs() -> FakeWindowManager {
+        FakeWindowManager::default()
+            .with_output("DP-1", 0, 0)
+            .with_output("DP-2", 0, 1080)
+            .with_output("HDMI-A-1", 1920, 0)
+            .with_workspace("1", "DP-1")
+            .with_workspace("2", "DP-2")
+            .with_workspace("3", "HDMI-A-1")
+            .showing("2")
+            .showing("3")
+            .focusing("1")
+    }
+
+    #[test]
+    fn outputs_can_be_reached_in_two_dimensions() {
+        let commands = run_on(stacked_outputs(), "move-focus-to output down");
+        assert_eq!(commands, focus("number 2"));
+        let commands = run_on(stacked_outputs(), "move-focus-to output right");
+        assert_eq!(commands, focus("number 3"));
+        let commands = run_on(stacked_outputs().focusing("3"), "move-focus-to output left");
+        assert_eq!(commands, focus("number 1"));
+    }
+
+    #[test]
+    fn outputs_must_overlap_to_be_adjacent() {
+        let commands = run_on(
+            stacked_outputs().focusing("2"),
+            "move-focus-to output right",
+        );
+        assert_eq!(commands, focus("number 2"));
+    }
+
+    #[test]
+    fn wrapping_goes_to_the_farthest_output_on_the_other_side() {
+        let commands = run_on(stacked_outputs(), "move-focus-to output up");
+        assert_eq!(commands, focus("number 1"));
+        let commands = run_on(stacked_outputs(), "move-focus-to output up --at-end wrap");
+        assert_eq!(commands, focus("number 2"));
+    }
+
+    #[test]
+    fn geometric_directions_only_apply_to_outputs() {
+        let mut wm = stacked_outputs();
+        let opt = opt("move-focus-to workspace left");
+        let error = run(&mut wm, &opt, &Config::default(), &mut History::default()).unwrap_err();
+        assert!(matches!(error, SwayspaceError::Unsupported(_)));
+    }
 }
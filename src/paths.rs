@@ -0,0 +1,17 @@
+use crate::error::{Result, SwayspaceError};
+use std::path::PathBuf;
+
+// Where per-session files live: the daemon's socket, the focus history...
+// Not the shared temporary directory: other users could take over the socket.
+pub fn runtime_file(name: &str) -> Result<PathBuf> {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(|dir| PathBuf::from(dir).join(name))
+        .ok_or(SwayspaceError::NoRuntimeDir)
+}
+
+pub fn config_file() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("swayspace").join("config.toml"))
+}
@@ -0,0 +1,662 @@
+use crate::backend::{self, CommandBatch, Output, Window, WindowManager, SCRATCHPAD};
+use crate::config::Settings;
+use crate::error::{Result, SwayspaceError};
+use crate::{AtEnd, Direction, Index};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::ops::RangeInclusive;
+
+// A workspace as swayspace navigates it: either one that exists, or one that
+// will be created when we move to it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Workspace {
+    pub num: Option<i32>,
+    pub name: String,
+    // None of them yet on those that do not exist
+    pub windows: usize,
+}
+
+impl Workspace {
+    pub fn numbered(num: i32) -> Self {
+        Self {
+            num: Some(num),
+            name: num.to_string(),
+            windows: 0,
+        }
+    }
+    fn from_wm(w: &backend::Workspace, windows: &[Window]) -> Self {
+        Self {
+            num: if w.num >= 0 { Some(w.num) } else { None },
+            name: w.name.clone(),
+            windows: count_windows(&w.name, windows),
+        }
+    }
+    // The name without the output prefix that `--prefix-output` gives it,
+    // e.g. `1` for `1:DP-1` on DP-1
+    pub fn label(&self, output: &str) -> &str {
+        self.name
+            .strip_suffix(output)
+            .and_then(|name| name.strip_suffix(':'))
+            .unwrap_or(&self.name)
+    }
+    // How sway commands refer to this workspace: plain numbers go through
+    // `number` so that they are created if needed, anything else is kept by
+    // name so that labels like `3:web` survive.
+    pub fn as_arg(&self) -> String {
+        match self.num {
+            Some(num) if self.name == num.to_string() => format!("number {}", num),
+            _ => quote(&self.name),
+        }
+    }
+}
+
+// Names are unique, and a workspace stays the same one as windows come and go
+impl PartialEq for Workspace {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Workspace {}
+
+// Numbered workspaces come first, by number, then purely named ones by name
+impl Ord for Workspace {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.num, other.num) {
+            (Some(a), Some(b)) => a.cmp(&b).then_with(|| self.name.cmp(&other.name)),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => self.name.cmp(&other.name),
+        }
+    }
+}
+
+impl PartialOrd for Workspace {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn count_windows(workspace: &str, windows: &[Window]) -> usize {
+    windows.iter().filter(|w| w.workspace == workspace).count()
+}
+
+// `3:web` renumbered to 5 is `5:web`
+fn relabel(name: &str, num: i32) -> String {
+    format!(
+        "{}{}",
+        num,
+        name.trim_start_matches(|c: char| c.is_ascii_digit())
+    )
+}
+
+// Groups own one workspace per output, named after both, e.g. `work@DP-1`
+pub fn group_workspace(group: &str, output: &str) -> Workspace {
+    Workspace {
+        num: None,
+        name: format!("{}@{}", group, output),
+        windows: 0,
+    }
+}
+
+pub fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// One output as `swayspace state` shows it
+#[derive(Debug, Serialize)]
+pub struct OutputState<'a> {
+    #[serde(flatten)]
+    pub output: &'a Output,
+    pub focused: bool,
+    pub visible_workspace: &'a Workspace,
+    // In the order that prev and next go through them
+    pub workspaces: Vec<&'a Workspace>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WindowManagerState {
+    current_workspace: Workspace,
+    // Sorted by `Workspace`'s ordering
+    workspaces_on_focused_output: Vec<Workspace>,
+    // Along with the name of their output
+    workspaces_on_unfocused_outputs: Vec<(String, Workspace)>,
+    // For each output in order of its x position, its visible workspace
+    visible_workspace_per_output: Vec<(Output, Workspace)>,
+    focused_output: String,
+    // Tiled and floating, in the order `get_windows` gives them
+    #[serde(skip)]
+    windows: Vec<Window>,
+}
+
+impl WindowManagerState {
+    pub fn from_wm(wm: &mut impl WindowManager) -> Result<Self> {
+        let (focused_output_name, windows) = wm.get_focused_output_and_windows()?;
+
+        let mut outputs = wm.get_outputs()?;
+        outputs.sort();
+
+        let mut all_workspaces = wm.get_workspaces()?;
+        let visible_workspaces = all_workspaces
+            .iter()
+            .filter(|w| w.visible)
+            .collect::<Vec<_>>();
+        let visible_workspace_per_output = outputs
+            .into_iter()
+            .filter_map(|o| {
+                visible_workspaces
+                    .iter()
+                    .find(|w| w.output == o.name)
+                    .map(|w| Workspace::from_wm(w, &windows))
+                    .map(|w| (o, w))
+            })
+            .collect();
+
+        let current_workspace = Workspace::from_wm(
+            all_workspaces
+                .iter()
+                .find(|w| w.focused)
+                .ok_or(SwayspaceError::NoFocusedWorkspace)?,
+            &windows,
+        );
+        let partition_point = all_workspaces
+            .iter_mut()
+            .partition_in_place(|w| w.output == focused_output_name);
+        let mut workspaces_on_focused_output = all_workspaces[0..partition_point]
+            .iter()
+            .map(|w| Workspace::from_wm(w, &windows))
+            .collect::<Vec<_>>();
+        workspaces_on_focused_output.sort_unstable();
+        let workspaces_on_unfocused_outputs = all_workspaces[partition_point..]
+            .iter()
+            .map(|w| (w.output.clone(), Workspace::from_wm(w, &windows)))
+            .collect::<Vec<_>>();
+        Ok(Self {
+            current_workspace,
+            workspaces_on_focused_output,
+            workspaces_on_unfocused_outputs,
+            visible_workspace_per_output,
+            focused_output: focused_output_name,
+            windows,
+        })
+    }
+    // Follows a focus change without asking sway. Returns false if `name` is
+    // not a workspace we know of, in which case only sway can tell.
+    pub fn focus_workspace(&mut self, name: &str) -> bool {
+        let workspace = match self.find_workspace(name) {
+            Some(workspace) => workspace.clone(),
+            None => return false,
+        };
+        let output = self
+            .workspaces_on_unfocused_outputs
+            .iter()
+            .find(|(_, w)| w.name == name)
+            .map_or_else(|| self.focused_output.clone(), |(o, _)| o.clone());
+        if output != self.focused_output {
+            let previous = std::mem::replace(&mut self.focused_output, output.clone());
+            let (here, elsewhere) = std::mem::take(&mut self.workspaces_on_unfocused_outputs)
+                .into_iter()
+                .chain(
+                    self.workspaces_on_focused_output
+                        .drain(..)
+                        .map(|w| (previous.clone(), w)),
+                )
+                .partition::<Vec<_>, _>(|(o, _)| *o == output);
+            self.workspaces_on_focused_output = here.into_iter().map(|(_, w)| w).collect();
+            self.workspaces_on_focused_output.sort_unstable();
+            self.workspaces_on_unfocused_outputs = elsewhere;
+        }
+        for (o, visible) in self.visible_workspace_per_output.iter_mut() {
+            if o.name == output {
+                *visible = workspace.clone();
+            }
+        }
+        self.current_workspace = workspace;
+        true
+    }
+    pub fn focus_window(&mut self, id: i64) {
+        for w in self.windows.iter_mut() {
+            w.focused = w.id == id;
+        }
+    }
+    // Windows come and go far more often than workspaces: this only takes a
+    // fresh look at the tree
+    pub fn refresh_windows(&mut self, wm: &mut impl WindowManager) -> Result<()> {
+        let windows = wm.get_windows()?;
+        let workspaces = self
+            .workspaces_on_focused_output
+            .iter_mut()
+            .chain(
+                self.workspaces_on_unfocused_outputs
+                    .iter_mut()
+                    .map(|(_, w)| w),
+            )
+            .chain(self.visible_workspace_per_output.iter_mut().map(|(_, w)| w))
+            .chain(std::iter::once(&mut self.current_workspace));
+        for w in workspaces {
+            w.windows = count_windows(&w.name, &windows);
+        }
+        self.windows = windows;
+        Ok(())
+    }
+    pub fn focused_output(&self) -> Option<&Output> {
+        self.visible_workspace_per_output
+            .iter()
+            .map(|(o, _)| o)
+            .find(|o| o.name == self.focused_output)
+    }
+    // Every output in order of its position, with its workspaces
+    pub fn outputs(&self) -> Vec<OutputState<'_>> {
+        self.visible_workspace_per_output
+            .iter()
+            .map(|(output, visible)| {
+                let focused = output.name == self.focused_output;
+                let workspaces = if focused {
+                    self.workspaces_on_focused_output.iter().collect()
+                } else {
+                    let mut workspaces = self
+                        .workspaces_on_unfocused_outputs
+                        .iter()
+                        .filter(|(o, _)| *o == output.name)
+                        .map(|(_, w)| w)
+                        .collect::<Vec<_>>();
+                    workspaces.sort_unstable();
+                    workspaces
+                };
+                OutputState {
+                    output,
+                    focused,
+                    visible_workspace: visible,
+                    workspaces,
+                }
+            })
+            .collect()
+    }
+    pub fn current_workspace(&self) -> &Workspace {
+        &self.current_workspace
+    }
+    // The window swayspace last showed from the scratchpad, whether it is
+    // still out or has been hidden again
+    pub fn shown_window(&self) -> Option<&Window> {
+        self.windows.iter().find(|w| w.shown)
+    }
+    pub fn find_workspace(&self, name: &str) -> Option<&Workspace> {
+        self.workspaces_on_focused_output
+            .iter()
+            .chain(self.workspaces_on_unfocused_outputs.iter().map(|(_, w)| w))
+            .find(|w| w.name == name)
+    }
+    fn next_workspace<'a>(&'a self, workspaces: impl Iterator<Item = &'a Workspace>) -> Workspace {
+        workspaces
+            .skip_while(|&w| *w != self.current_workspace)
+            .nth(1)
+            .unwrap_or(&self.current_workspace)
+            .clone()
+    }
+    // Numbers taken on other outputs must be skipped when creating workspaces,
+    // unless their names tell the outputs apart
+    fn is_free(&self, num: i32, prefixed: bool) -> bool {
+        prefixed
+            || !self
+                .workspaces_on_unfocused_outputs
+                .iter()
+                .any(|(_, w)| w.num == Some(num))
+    }
+    // The highest number in use on the focused output within its range
+    fn max_in_range(&self, range: &RangeInclusive<i32>) -> i32 {
+        self.workspaces_on_focused_output
+            .iter()
+            .filter_map(|w| w.num)
+            .filter(|num| range.contains(num))
+            .max()
+            .unwrap_or(range.start() - 1)
+    }
+    // A workspace that does not exist yet on the focused output
+    fn created(&self, num: i32, prefixed: bool) -> Workspace {
+        if prefixed {
+            Workspace {
+                num: Some(num),
+                name: format!("{}:{}", num, self.focused_output),
+                windows: 0,
+            }
+        } else {
+            Workspace::numbered(num)
+        }
+    }
+    // Numbers that new workspaces can take on the focused output, in order.
+    // A full range has nowhere left to grow.
+    fn new_workspaces<'a>(
+        &'a self,
+        range: &RangeInclusive<i32>,
+        prefixed: bool,
+    ) -> impl Iterator<Item = Workspace> + 'a {
+        (self.max_in_range(range) + 1..=*range.end())
+            .filter(move |&num| self.is_free(num, prefixed))
+            .map(move |num| self.created(num, prefixed))
+    }
+    // Every number of the focused output's range up to the highest one in use
+    // that is not taken elsewhere, whether it exists yet or not, along with
+    // the workspaces that live outside of it
+    fn dynamic_workspaces(&self, range: &RangeInclusive<i32>, prefixed: bool) -> Vec<Workspace> {
+        let exists = |num: i32| {
+            self.workspaces_on_focused_output
+                .iter()
+                .any(|w| w.num == Some(num))
+        };
+        let mut workspaces = self
+            .workspaces_on_focused_output
+            .iter()
+            .cloned()
+            .chain(
+                (*range.start()..=self.max_in_range(range))
+                    .filter(|&num| !exists(num) && self.is_free(num, prefixed))
+                    .map(|num| self.created(num, prefixed)),
+            )
+            .collect::<Vec<_>>();
+        workspaces.sort_unstable();
+        workspaces
+    }
+    // Renames closing the gaps between the numbered workspaces of the focused
+    // output, keeping their order and labels, from the start of its range.
+    // Those outside the range are only brought in if they all fit. Sway moves
+    // the windows along. Renames never target a name still in use: those
+    // moving down go first, lowest first, then those moving up, highest first.
+    pub fn renumbering(&self, settings: &Settings) -> CommandBatch {
+        let range = &settings.range;
+        let free = || {
+            range
+                .clone()
+                .filter(|&num| self.is_free(num, settings.prefix_output))
+        };
+        let numbered = self
+            .workspaces_on_focused_output
+            .iter()
+            .filter_map(|w| Some((w, w.num?)))
+            .collect::<Vec<_>>();
+        let all_fit = free().take(numbered.len()).count() == numbered.len();
+        let (down, up): (Vec<_>, Vec<_>) = numbered
+            .into_iter()
+            .filter(|&(_, num)| all_fit || range.contains(&num))
+            .zip(free())
+            .filter(|&((_, num), target)| target != num)
+            .partition(|&((_, num), target)| target < num);
+        // Prefixed numbers are only free on this output: the name must say so
+        let renamed = |w: &Workspace, target: i32| {
+            if settings.prefix_output {
+                let label = relabel(w.label(&self.focused_output), target);
+                format!("{}:{}", label, self.focused_output)
+            } else {
+                relabel(&w.name, target)
+            }
+        };
+        down.into_iter()
+            .chain(up.into_iter().rev())
+            .map(|((w, _), target)| {
+                format!(
+                    "rename workspace {} to {}",
+                    quote(&w.name),
+                    quote(&renamed(w, target))
+                )
+            })
+            .collect()
+    }
+    // The focused output's workspaces as prev and next go through them, gaps
+    // included if dynamic, and, if requested, the one next would create
+    pub fn workspaces_to_cycle(&self, settings: &Settings, with_new_one: bool) -> Vec<Workspace> {
+        let mut workspaces = if settings.dynamic {
+            self.dynamic_workspaces(&settings.range, settings.prefix_output)
+        } else {
+            self.workspaces_on_focused_output.clone()
+        };
+        // The current one stays, empty or not, so that we know where we are
+        if settings.skip_empty {
+            workspaces.retain(|w| w.windows > 0 || *w == self.current_workspace);
+        }
+        if with_new_one {
+            workspaces.extend(
+                self.new_workspaces(&settings.range, settings.prefix_output)
+                    .next(),
+            );
+        }
+        workspaces
+    }
+    // Anything but `Next` goes backwards: toggling is rejected before we get
+    // here. Nothing can be created before the first workspace, so going
+    // backwards past it wraps when creating.
+    pub fn cycle_through_workspaces_on_focused_output(
+        &self,
+        settings: &Settings,
+        dir: Direction,
+    ) -> Workspace {
+        let forwards = matches!(dir, Direction::Next);
+        let creating = settings.at_end == AtEnd::Create;
+        let mut workspaces = self.workspaces_to_cycle(settings, forwards && creating);
+        if !forwards {
+            workspaces.reverse();
+        }
+        match settings.at_end {
+            AtEnd::Wrap => self.next_workspace(workspaces.iter().cycle()),
+            AtEnd::Create if !forwards => self.next_workspace(workspaces.iter().cycle()),
+            _ => self.next_workspace(workspaces.iter()),
+        }
+    }
+    // Every workspace, output by output in order of their position
+    pub fn cycle_through_all_workspaces(&self, settings: &Settings, dir: Direction) -> Workspace {
+        let mut workspaces = self
+            .outputs()
+            .into_iter()
+            .flat_map(|o| o.workspaces)
+            .filter(|&w| !settings.skip_empty || w.windows > 0 || *w == self.current_workspace)
+            .collect::<Vec<_>>();
+        if !matches!(dir, Direction::Next) {
+            workspaces.reverse();
+        }
+        match settings.at_end {
+            AtEnd::Wrap => self.next_workspace(workspaces.into_iter().cycle()),
+            _ => self.next_workspace(workspaces.into_iter()),
+        }
+    }
+    // Every window, workspace by workspace in the order AllWorkspaces goes
+    // through them, keeping to `app_id` if given. The focused window always
+    // counts so that we know where we are; without one, we start from the
+    // first window that way.
+    pub fn cycle_through_windows(&self, settings: &Settings, dir: Direction) -> Option<Window> {
+        let mut windows = self
+            .outputs()
+            .into_iter()
+            .flat_map(|o| o.workspaces)
+            .flat_map(|ws| self.windows.iter().filter(move |w| w.workspace == ws.name))
+            .filter(|w| w.focused || settings.app_id.is_none() || w.app_id == settings.app_id)
+            .collect::<Vec<_>>();
+        if !matches!(dir, Direction::Next) {
+            windows.reverse();
+        }
+        let next = match windows.iter().position(|w| w.focused) {
+            Some(focused) => windows.get(focused + 1),
+            None => return windows.first().map(|&w| w.clone()),
+        };
+        match settings.at_end {
+            AtEnd::Wrap => next.or_else(|| windows.first()),
+            _ => next,
+        }
+        .map(|&w| w.clone())
+    }
+    // Hidden scratchpad windows in order of their ids, that is of creation,
+    // from the one shown last on, keeping to `app_id` if given
+    pub fn cycle_through_scratchpad(&self, settings: &Settings, dir: Direction) -> Option<Window> {
+        let forwards = matches!(dir, Direction::Next);
+        let mut hidden = self
+            .windows
+            .iter()
+            .filter(|w| w.workspace == SCRATCHPAD)
+            .filter(|w| settings.app_id.is_none() || w.app_id == settings.app_id)
+            .collect::<Vec<_>>();
+        hidden.sort_by_key(|w| w.id);
+        if !forwards {
+            hidden.reverse();
+        }
+        let ahead = |w: &&&Window| match self.shown_window() {
+            Some(shown) if forwards => w.id > shown.id,
+            Some(shown) => w.id < shown.id,
+            None => true,
+        };
+        match settings.at_end {
+            AtEnd::Wrap => hidden.iter().find(ahead).or_else(|| hidden.first()),
+            _ => hidden.iter().find(ahead),
+        }
+        .map(|&w| w.clone())
+    }
+    // Outside of any group, we start from the first one that way
+    pub fn cycle_through_groups<'a>(
+        &self,
+        groups: &'a [String],
+        dir: Direction,
+        at_end: AtEnd,
+    ) -> Option<&'a String> {
+        let mut groups = groups.iter().collect::<Vec<_>>();
+        if !matches!(dir, Direction::Next) {
+            groups.reverse();
+        }
+        let current = groups.iter().position(|group| {
+            group_workspace(group, &self.focused_output).name == self.current_workspace.name
+        });
+        match (current, at_end) {
+            (None, _) => groups.first().copied(),
+            (Some(i), AtEnd::Wrap) => groups.get(i + 1).or_else(|| groups.first()).copied(),
+            (Some(i), _) => groups.get(i + 1).copied(),
+        }
+    }
+    // Positions past the end stay put unless `dynamic`, in which case they
+    // count on from there into new workspaces, the way next would create them
+    pub fn nth_workspace_on_focused_output(&self, index: Index, settings: &Settings) -> Workspace {
+        let (range, prefixed) = (&settings.range, settings.prefix_output);
+        let workspaces = if settings.dynamic {
+            self.dynamic_workspaces(range, prefixed)
+        } else {
+            self.workspaces_on_focused_output.clone()
+        };
+        match index {
+            Index::First => workspaces.first().cloned(),
+            Index::Last => workspaces.last().cloned(),
+            Index::Nth(n) if n <= workspaces.len() => Some(workspaces[n - 1].clone()),
+            Index::Nth(n) if settings.dynamic => self
+                .new_workspaces(range, prefixed)
+                .nth(n - workspaces.len() - 1),
+            Index::Nth(_) => None,
+        }
+        .unwrap_or_else(|| self.current_workspace.clone())
+    }
+    // The output showing `workspace`, if any
+    pub fn output_showing(&self, workspace: &Workspace) -> Option<&Output> {
+        self.visible_workspace_per_output
+            .iter()
+            .find(|(_, w)| w == workspace)
+            .map(|(o, _)| o)
+    }
+    // What the current workspace becomes when moved to `output`: its number
+    // must belong to the output's range and not be used there already, or it
+    // takes the first one past those in use, free everywhere else too unless
+    // `prefixed`, in which case the name follows it to `output`.
+    pub fn moved_to(&self, output: &str, range: &RangeInclusive<i32>, prefixed: bool) -> Workspace {
+        let current = &self.current_workspace;
+        let num = match current.num {
+            Some(num) => num,
+            None => return current.clone(),
+        };
+        let theirs = self
+            .workspaces_on_unfocused_outputs
+            .iter()
+            .filter(|(o, _)| o == output)
+            .filter_map(|(_, w)| w.num)
+            .collect::<Vec<_>>();
+        let taken = |num: i32| {
+            if prefixed {
+                theirs.contains(&num)
+            } else {
+                !self.is_free(num, false)
+                    || self
+                        .workspaces_on_focused_output
+                        .iter()
+                        .any(|w| w != current && w.num == Some(num))
+            }
+        };
+        let start = theirs
+            .iter()
+            .filter(|num| range.contains(num))
+            .max()
+            .map_or(*range.start(), |max| max + 1);
+        let num = if range.contains(&num) && !theirs.contains(&num) {
+            num
+        } else {
+            match (start..=*range.end()).find(|&num| !taken(num)) {
+                Some(num) => num,
+                // Better a duplicate number than refusing to move
+                None => return current.clone(),
+            }
+        };
+        let name = relabel(current.label(&self.focused_output), num);
+        Workspace {
+            num: Some(num),
+            name: if prefixed {
+                format!("{}:{}", name, output)
+            } else {
+                name
+            },
+            windows: current.windows,
+        }
+    }
+    fn visible_workspaces(&self) -> impl DoubleEndedIterator<Item = &Workspace> + Clone {
+        self.visible_workspace_per_output.iter().map(|(_, w)| w)
+    }
+    pub fn cycle_through_outputs(&self, dir: Direction, at_end: AtEnd) -> Workspace {
+        match (dir, at_end) {
+            (Direction::Next, AtEnd::Wrap) => {
+                self.next_workspace(self.visible_workspaces().cycle())
+            }
+            (Direction::Next, _) => self.next_workspace(self.visible_workspaces()),
+            (_, AtEnd::Wrap) => self.next_workspace(self.visible_workspaces().rev().cycle()),
+            (_, _) => self.next_workspace(self.visible_workspaces().rev()),
+        }
+    }
+    // The visible workspace of the closest output in a geometric direction,
+    // among those that overlap the focused one on the other axis. With
+    // `wrap`, running out of outputs goes to the farthest one on the other
+    // side, otherwise we stay put.
+    pub fn adjacent_output(&self, dir: Direction, wrap: bool) -> Workspace {
+        let current = match self.focused_output() {
+            Some(current) => current,
+            None => return self.current_workspace.clone(),
+        };
+        // How far ahead of the focused output `o` starts, negative if behind
+        let distance = |o: &Output| match dir {
+            Direction::Left => current.x_pos - (o.x_pos + o.width),
+            Direction::Right => o.x_pos - (current.x_pos + current.width),
+            Direction::Up => current.y_pos - (o.y_pos + o.height),
+            Direction::Down => o.y_pos - (current.y_pos + current.height),
+            _ => unreachable!("{:?} is not a geometric direction", dir),
+        };
+        let overlap = |o: &Output| match dir {
+            Direction::Left | Direction::Right => {
+                (o.y_pos + o.height).min(current.y_pos + current.height)
+                    - o.y_pos.max(current.y_pos)
+            }
+            _ => {
+                (o.x_pos + o.width).min(current.x_pos + current.width) - o.x_pos.max(current.x_pos)
+            }
+        };
+        let aligned = || {
+            self.visible_workspace_per_output
+                .iter()
+                .filter(|(o, _)| o.name != current.name && overlap(o) > 0)
+        };
+        let closest = |candidates: Vec<&(Output, Workspace)>| {
+            candidates
+                .into_iter()
+                .min_by_key(|(o, _)| (distance(o), -overlap(o)))
+                .map(|(_, w)| w.clone())
+        };
+        closest(aligned().filter(|(o, _)| distance(o) >= 0).collect())
+            .or_else(|| wrap.then(|| closest(aligned().collect()))?)
+            .unwrap_or_else(|| self.current_workspace.clone())
+    }
+}